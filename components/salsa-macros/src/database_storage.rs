@@ -11,6 +11,36 @@ pub(crate) fn database(args: TokenStream, input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as ItemStruct);
 
     let query_groups = &args.query_groups;
+
+    // The storage fields and key variants are named after the last
+    // path segment of each group, so those must be distinct.
+    for (index, query_group) in query_groups.iter().enumerate() {
+        let name = query_group.name();
+        let snake_name = name.to_string().to_snake_case();
+        if let Some(previous) = query_groups
+            .iter()
+            .take(index)
+            .find(|previous| previous.name().to_string().to_snake_case() == snake_name)
+        {
+            let previous_path = previous
+                .group_path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::");
+            return syn::Error::new(
+                name.span(),
+                format!(
+                    "query group `{}` has the same name as `{}`; \
+                     import one of them under another name with `use ... as ...`",
+                    name, previous_path,
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
     let database_name = &input.ident;
     let visibility = &input.vis;
 
@@ -174,7 +204,7 @@ pub(crate) fn database(args: TokenStream, input: TokenStream) -> TokenStream {
 
     if std::env::var("SALSA_DUMP").is_ok() {
         println!("~~~ database_storage");
-        println!("{}", output.to_string());
+        println!("{}", output);
         println!("~~~ database_storage");
     }
 
@@ -220,11 +250,3 @@ impl Parse for QueryGroup {
         Ok(QueryGroup { group_path })
     }
 }
-
-struct Nothing;
-
-impl Parse for Nothing {
-    fn parse(_input: ParseStream) -> syn::Result<Self> {
-        Ok(Nothing)
-    }
-}
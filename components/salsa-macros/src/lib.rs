@@ -57,6 +57,31 @@ mod query_group;
 ///     indicates the function to call when a query must be
 ///     recomputed. The default is to call a function in the same
 ///     module with the same name as the query.
+///   - `#[salsa::canonicalize(path::to::my_fn)]` -- for a derived
+///     query, maps each key to a canonical form (`fn(Key) -> Key`)
+///     before the memoized value is looked up, so that equivalent
+///     keys share a single memo. For queries with zero or several
+///     inputs, the function receives and returns the key tuple.
+///   - `#[salsa::validate(path::to::my_fn)]` -- for a derived query,
+///     names a function `fn(&DB, &Key, &Value)` that is invoked on
+///     each newly computed value in debug builds, to assert
+///     invariants of the result. A panic in it reports the query
+///     stack, like a panic in the query itself. The queries it reads
+///     do not become dependencies of the validated query.
+///   - `#[salsa::recovery(path::to::my_fn)]` -- for a derived query,
+///     names a function `fn(&DB, &[DB::DatabaseKey], &Key) -> Value`
+///     that supplies a fallback value when fetching the query would
+///     complete a cycle, instead of panicking. It receives the
+///     participants of the cycle, outermost first. The query that
+///     received the fallback is re-executed in each new revision.
+///   - `#[salsa::projection(of = parent_query, via = path::to::my_fn)]`
+///     -- for a derived query, computes the value as
+///     `my_fn(&db.parent_query(keys))`, where `parent_query` is a query
+///     of the same group with the same keys and `my_fn` is a
+///     `fn(&ParentValue) -> Value`. Extracting a small piece of a
+///     large value this way acts as a "firewall": dependents of the
+///     projection are only invalidated when the extracted piece
+///     changes. Cannot be combined with `invoke`.
 ///   - `#[query_type(MyQueryTypeName)]` specifies the name of the
 ///     dummy struct created fo the query. Default is the name of the
 ///     query, in camel case, plus the word "Query" (e.g.,
@@ -121,6 +146,30 @@ mod query_group;
 ///     fn my_query(&self, input: u32) -> u64;
 /// }
 /// ```
+///
+/// Such mistakes are reported as compiler errors pointing at the offending
+/// attribute or argument, as is an unknown `salsa::` attribute:
+///
+/// ```compile_fail
+/// # use salsa_macros as salsa;
+/// #[salsa::query_group]
+/// trait CodegenDatabase {
+///     #[salsa::memoised]
+///     fn my_query(&self, input: u32) -> u64;
+/// }
+/// ```
+///
+/// Likewise, two queries whose generated query types coincide are
+/// reported; one of them must be renamed with `#[salsa::query_type]`:
+///
+/// ```compile_fail
+/// # use salsa_macros as salsa;
+/// #[salsa::query_group]
+/// trait CodegenDatabase {
+///     fn my_query(&self, input: u32) -> u64;
+///     fn my_query_(&self, input: u32) -> u64;
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn query_group(args: TokenStream, input: TokenStream) -> TokenStream {
     query_group::query_group(args, input)
@@ -141,6 +190,11 @@ pub fn query_group(args: TokenStream, input: TokenStream) -> TokenStream {
 /// attribute, the struct needs to have a `runtime` field (of type
 /// [`salsa::Runtime`]) and to implement the `salsa::Database` trait.
 ///
+/// The storage of each group is named after the last segment of its
+/// path, so two groups with the same name (from different modules) must
+/// be imported under distinct names, e.g. `use other::MyQueryGroup1 as
+/// OtherQueryGroup1;`.
+///
 /// See [the `hello_world` example][hw] for more details.
 ///
 /// [`salsa::Runtime`]: struct.Runtime.html
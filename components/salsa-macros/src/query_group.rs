@@ -5,10 +5,11 @@ use heck::CamelCase;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::ToTokens;
+use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::{
-    parse_macro_input, parse_quote, Attribute, FnArg, Ident, ItemTrait, Path,
-    ReturnType, Token, TraitBound, TraitBoundModifier, TraitItem, Type, TypeParamBound,
+    parse_macro_input, parse_quote, Attribute, Error, FnArg, Ident, ItemTrait, Path, ReturnType,
+    Token, TraitBound, TraitBoundModifier, TraitItem, Type, TypeParamBound,
 };
 
 /// Implementation for `[salsa::query_group]` decorator.
@@ -18,14 +19,29 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
     // println!("args: {:#?}", args);
     // println!("input: {:#?}", input);
 
+    match query_group_impl(group_struct, input) {
+        Ok(output) => output.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn query_group_impl(
+    group_struct: Ident,
+    input: ItemTrait,
+) -> syn::Result<proc_macro2::TokenStream> {
     let (trait_attrs, salsa_attrs) = filter_attrs(input.attrs);
     let mut requires: Punctuated<Path, Token![+]> = Punctuated::new();
-    for SalsaAttr { name, tts } in salsa_attrs {
+    for SalsaAttr { name, span, tts } in salsa_attrs {
         match name.as_str() {
             "requires" => {
-                requires.push(parse_macro_input!(tts as Parenthesized<syn::Path>).0);
+                requires.push(syn::parse::<Parenthesized<syn::Path>>(tts)?.0);
+            }
+            _ => {
+                return Err(Error::new(
+                    span,
+                    format!("unknown salsa attribute `{}`", name),
+                ))
             }
-            _ => panic!("unknown salsa attribute `{}`", name),
         }
     }
 
@@ -36,154 +52,265 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
     // Decompose the trait into the corresponding queries.
     let mut queries = vec![];
     for item in input.items {
-        match item {
-            TraitItem::Method(method) => {
-                let mut storage = QueryStorage::Memoized;
-                let mut invoke = None;
-                let mut query_type = Ident::new(
-                    &format!("{}Query", method.sig.ident.to_string().to_camel_case()),
-                    Span::call_site(),
-                );
-                let mut num_storages = 0;
-
-                // Extract attributes.
-                let (attrs, salsa_attrs) = filter_attrs(method.attrs);
-                for SalsaAttr { name, tts } in salsa_attrs {
-                    match name.as_str() {
-                        "memoized" => {
-                            storage = QueryStorage::Memoized;
-                            num_storages += 1;
-                        }
-                        "volatile" => {
-                            storage = QueryStorage::Volatile;
-                            num_storages += 1;
-                        }
-                        "dependencies" => {
-                            storage = QueryStorage::Dependencies;
-                            num_storages += 1;
-                        }
-                        "input" => {
-                            storage = QueryStorage::Input;
-                            num_storages += 1;
-                        }
-                        "interned" => {
-                            storage = QueryStorage::Interned;
-                            num_storages += 1;
-                        }
-                        "invoke" => {
-                            invoke = Some(parse_macro_input!(tts as Parenthesized<syn::Path>).0);
-                        }
-                        "query_type" => {
-                            query_type = parse_macro_input!(tts as Parenthesized<Ident>).0;
-                        }
-                        "transparent" => {
-                            storage = QueryStorage::Transparent;
-                            num_storages += 1;
-                        }
-                        _ => panic!("unknown salsa attribute `{}`", name),
+        if let TraitItem::Method(method) = item {
+            let mut storage = QueryStorage::Memoized;
+            let mut invoke = None;
+            let mut projection = None;
+            let mut canonicalize = None;
+            let mut validate = None;
+            let mut recovery = None;
+            let mut query_type = Ident::new(
+                &format!("{}Query", method.sig.ident.to_string().to_camel_case()),
+                Span::call_site(),
+            );
+            let mut num_storages = 0;
+            let mut storage_span = None;
+
+            // Extract attributes.
+            let (attrs, salsa_attrs) = filter_attrs(method.attrs);
+            for SalsaAttr { name, span, tts } in salsa_attrs {
+                match name.as_str() {
+                    "memoized" => {
+                        storage = QueryStorage::Memoized;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    "volatile" => {
+                        storage = QueryStorage::Volatile;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    "dependencies" => {
+                        storage = QueryStorage::Dependencies;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    "input" => {
+                        storage = QueryStorage::Input;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    "interned" => {
+                        storage = QueryStorage::Interned;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    "invoke" => {
+                        invoke = Some(syn::parse::<Parenthesized<syn::Path>>(tts)?.0);
+                    }
+                    "projection" => {
+                        projection = Some(syn::parse::<Parenthesized<Projection>>(tts)?.0);
+                    }
+                    "canonicalize" => {
+                        canonicalize = Some(syn::parse::<Parenthesized<syn::Path>>(tts)?.0);
+                    }
+                    "validate" => {
+                        validate = Some(syn::parse::<Parenthesized<syn::Path>>(tts)?.0);
+                    }
+                    "recovery" => {
+                        recovery = Some(syn::parse::<Parenthesized<syn::Path>>(tts)?.0);
+                    }
+                    "query_type" => {
+                        query_type = syn::parse::<Parenthesized<Ident>>(tts)?.0;
+                    }
+                    "transparent" => {
+                        storage = QueryStorage::Transparent;
+                        num_storages += 1;
+                        storage_span = Some(span);
+                    }
+                    _ => {
+                        return Err(Error::new(
+                            span,
+                            format!("unknown salsa attribute `{}`", name),
+                        ))
                     }
                 }
+            }
 
-                // Check attribute combinations.
-                if num_storages > 1 {
-                    panic!("multiple storage attributes specified");
+            // Check attribute combinations.
+            if num_storages > 1 {
+                return Err(Error::new(
+                    storage_span.unwrap(),
+                    "multiple storage attributes specified",
+                ));
+            }
+            if let (Some(invoke), QueryStorage::Input) = (&invoke, &storage) {
+                return Err(Error::new_spanned(
+                    invoke,
+                    "#[salsa::invoke] cannot be set on #[salsa::input] queries",
+                ));
+            }
+            if let Some(projection) = &projection {
+                if !storage.needs_query_function() {
+                    return Err(Error::new(
+                        projection.span,
+                        "#[salsa::projection] can only be set on derived queries",
+                    ));
                 }
-                if invoke.is_some() && storage == QueryStorage::Input {
-                    panic!("#[salsa::invoke] cannot be set on #[salsa::input] queries");
+                if let Some(invoke) = &invoke {
+                    return Err(Error::new_spanned(
+                        invoke,
+                        "#[salsa::invoke] cannot be combined with #[salsa::projection]",
+                    ));
                 }
+            }
+            if let Some(canonicalize) = canonicalize
+                .as_ref()
+                .filter(|_| !storage.needs_query_function())
+            {
+                return Err(Error::new_spanned(
+                    canonicalize,
+                    "#[salsa::canonicalize] can only be set on derived queries",
+                ));
+            }
+            if let Some(validate) = validate
+                .as_ref()
+                .filter(|_| !storage.needs_query_function())
+            {
+                return Err(Error::new_spanned(
+                    validate,
+                    "#[salsa::validate] can only be set on derived queries",
+                ));
+            }
+            if let Some(recovery) = recovery
+                .as_ref()
+                .filter(|_| !storage.needs_query_function())
+            {
+                return Err(Error::new_spanned(
+                    recovery,
+                    "#[salsa::recovery] can only be set on derived queries",
+                ));
+            }
 
-                // Extract keys.
-                let mut iter = method.sig.decl.inputs.iter();
-                match iter.next() {
-                    Some(FnArg::SelfRef(sr)) if sr.mutability.is_none() => (),
-                    _ => panic!(
+            // Extract keys.
+            let mut iter = method.sig.decl.inputs.iter();
+            match iter.next() {
+                Some(FnArg::SelfRef(sr)) if sr.mutability.is_none() => (),
+                arg => {
+                    let message = format!(
                         "first argument of query `{}` must be `&self`",
                         method.sig.ident
-                    ),
+                    );
+                    return Err(match arg {
+                        Some(arg) => Error::new_spanned(arg, message),
+                        None => Error::new(method.sig.ident.span(), message),
+                    });
                 }
-                let mut keys = vec![];
-                for arg in iter {
-                    match *arg {
-                        FnArg::Captured(ref arg) => {
-                            keys.push(arg.ty.clone());
-                        }
-                        ref a => panic!("unsupported argument `{:?}` of `{}`", a, method.sig.ident),
+            }
+            let mut keys = vec![];
+            for arg in iter {
+                match *arg {
+                    FnArg::Captured(ref arg) => {
+                        keys.push(arg.ty.clone());
+                    }
+                    ref a => {
+                        return Err(Error::new_spanned(
+                            a,
+                            format!("unsupported argument of query `{}`", method.sig.ident),
+                        ))
                     }
                 }
+            }
 
-                // Extract value.
-                let value = match method.sig.decl.output {
-                    ReturnType::Type(_, ref ty) => ty.as_ref().clone(),
-                    ref r => panic!(
-                        "unsupported return type `{:?}` of `{}`",
-                        r, method.sig.ident
-                    ),
-                };
-
-                // For `#[salsa::interned]` keys, we create a "lookup key" automatically.
-                //
-                // For a query like:
-                //
-                //     fn foo(&self, x: Key1, y: Key2) -> u32
-                //
-                // we would create
-                //
-                //     fn lookup_foo(&self, x: u32) -> (Key1, Key2)
-                let lookup_query = if let QueryStorage::Interned = storage {
-                    let lookup_query_type = Ident::new(
-                        &format!(
-                            "{}LookupQuery",
-                            method.sig.ident.to_string().to_camel_case()
-                        ),
-                        Span::call_site(),
-                    );
-                    let lookup_fn_name = Ident::new(
-                        &format!("lookup_{}", method.sig.ident.to_string()),
+            // Extract value.
+            let value = match method.sig.decl.output {
+                ReturnType::Type(_, ref ty) => ty.as_ref().clone(),
+                ReturnType::Default => {
+                    return Err(Error::new(
                         method.sig.ident.span(),
-                    );
-                    let keys = &keys;
-                    let lookup_value: Type = parse_quote!((#(#keys),*));
-                    let lookup_keys = vec![value.clone()];
-                    Some(Query {
-                        query_type: lookup_query_type,
-                        fn_name: lookup_fn_name,
-                        attrs: vec![], // FIXME -- some automatically generated docs on this method?
-                        storage: QueryStorage::InternedLookup {
-                            intern_query_type: query_type.clone(),
-                        },
-                        keys: lookup_keys,
-                        value: lookup_value,
-                        invoke: None,
-                    })
-                } else {
-                    None
-                };
-
-                queries.push(Query {
-                    query_type,
-                    fn_name: method.sig.ident,
-                    attrs,
-                    storage,
-                    keys,
-                    value,
-                    invoke,
-                });
+                        format!("query `{}` must have a return type", method.sig.ident),
+                    ))
+                }
+            };
+
+            // For `#[salsa::interned]` keys, we create a "lookup key" automatically.
+            //
+            // For a query like:
+            //
+            //     fn foo(&self, x: Key1, y: Key2) -> u32
+            //
+            // we would create
+            //
+            //     fn lookup_foo(&self, x: u32) -> (Key1, Key2)
+            let lookup_query = if let QueryStorage::Interned = storage {
+                let lookup_query_type = Ident::new(
+                    &format!(
+                        "{}LookupQuery",
+                        method.sig.ident.to_string().to_camel_case()
+                    ),
+                    Span::call_site(),
+                );
+                let lookup_fn_name = Ident::new(
+                    &format!("lookup_{}", method.sig.ident),
+                    method.sig.ident.span(),
+                );
+                let keys = &keys;
+                let lookup_value: Type = parse_quote!((#(#keys),*));
+                let lookup_keys = vec![value.clone()];
+                Some(Query {
+                    query_type: lookup_query_type,
+                    fn_name: lookup_fn_name,
+                    attrs: vec![], // FIXME -- some automatically generated docs on this method?
+                    storage: QueryStorage::InternedLookup {
+                        intern_query_type: query_type.clone(),
+                    },
+                    keys: lookup_keys,
+                    value: lookup_value,
+                    invoke: None,
+                    projection: None,
+                    canonicalize: None,
+                    validate: None,
+                    recovery: None,
+                })
+            } else {
+                None
+            };
+
+            queries.push(Query {
+                query_type,
+                fn_name: method.sig.ident,
+                attrs,
+                storage,
+                keys,
+                value,
+                invoke,
+                projection,
+                canonicalize,
+                validate,
+                recovery,
+            });
 
-                queries.extend(lookup_query);
+            queries.extend(lookup_query);
+        }
+    }
+
+    // Generated names are derived mechanically (e.g., `foo` and `foo_`
+    // both yield `FooQuery`), so report collisions here rather than
+    // as duplicate definitions in the expansion.
+    for (index, query) in queries.iter().enumerate() {
+        for previous in &queries[..index] {
+            if previous.query_type == query.query_type {
+                return Err(Error::new(
+                    query.fn_name.span(),
+                    format!(
+                        "query type `{}` of `{}` collides with the one of `{}`; \
+                         use `#[salsa::query_type(...)]` to rename it",
+                        query.query_type, query.fn_name, previous.fn_name,
+                    ),
+                ));
+            }
+            if previous.fn_name == query.fn_name {
+                return Err(Error::new(
+                    query.fn_name.span(),
+                    format!("query `{}` is defined more than once", query.fn_name),
+                ));
             }
-            _ => (),
         }
     }
 
-    let group_key = Ident::new(
-        &format!("{}GroupKey__", trait_name.to_string()),
-        Span::call_site(),
-    );
+    let group_key = Ident::new(&format!("{}GroupKey__", trait_name), Span::call_site());
 
-    let group_storage = Ident::new(
-        &format!("{}GroupStorage__", trait_name.to_string()),
-        Span::call_site(),
-    );
+    let group_storage = Ident::new(&format!("{}GroupStorage__", trait_name), Span::call_site());
 
     let mut query_fn_declarations = proc_macro2::TokenStream::new();
     let mut query_fn_definitions = proc_macro2::TokenStream::new();
@@ -370,6 +497,20 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
         };
         let keys = &query.keys;
         let value = &query.value;
+        let description = query.description();
+        let description = if description.is_empty() {
+            quote! {}
+        } else {
+            quote! { const DESCRIPTION: &'static str = #description; }
+        };
+        let canonicalize_key = match &query.canonicalize {
+            Some(canonicalize) => quote! {
+                fn canonicalize_key(key: Self::Key) -> Self::Key {
+                    #canonicalize(key)
+                }
+            },
+            None => quote! {},
+        };
 
         // Emit the query struct and implement the Query trait on it.
         output.extend(quote! {
@@ -396,6 +537,10 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
                 fn group_key(key: Self::Key) -> Self::GroupKey {
                     #group_key::#fn_name(key)
                 }
+
+                #description
+
+                #canonicalize_key
             }
         });
 
@@ -410,7 +555,39 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
             } else {
                 quote! { (#(#key_names),*) }
             };
-            let invoke = query.invoke_tt();
+            let body = match &query.projection {
+                Some(Projection { of, via, .. }) => quote! {
+                    #via(&<DB as #trait_name>::#of(db, #(#key_names),*))
+                },
+                None => {
+                    let invoke = query.invoke_tt();
+                    quote! { #invoke(db, #(#key_names),*) }
+                }
+            };
+            let validate = match &query.validate {
+                Some(validate) => quote! {
+                    fn validate(
+                        db: &DB,
+                        key: &<Self as salsa::Query<DB>>::Key,
+                        value: &<Self as salsa::Query<DB>>::Value,
+                    ) {
+                        #validate(db, key, value)
+                    }
+                },
+                None => quote! {},
+            };
+            let recover = match &query.recovery {
+                Some(recovery) => quote! {
+                    fn recover(
+                        db: &DB,
+                        cycle: &[<DB as salsa::plumbing::DatabaseStorageTypes>::DatabaseKey],
+                        key: &<Self as salsa::Query<DB>>::Key,
+                    ) -> Option<<Self as salsa::Query<DB>>::Value> {
+                        Some(#recovery(db, cycle, key))
+                    }
+                },
+                None => quote! {},
+            };
             output.extend(quote_spanned! {span=>
                 impl<DB> salsa::plumbing::QueryFunction<DB> for #qt
                 where
@@ -420,8 +597,12 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
                 {
                     fn execute(db: &DB, #key_pattern: <Self as salsa::Query<DB>>::Key)
                         -> <Self as salsa::Query<DB>>::Value {
-                        #invoke(db, #(#key_names),*)
+                        #body
                     }
+
+                    #validate
+
+                    #recover
                 }
             });
         }
@@ -507,15 +688,16 @@ pub(crate) fn query_group(args: TokenStream, input: TokenStream) -> TokenStream
 
     if std::env::var("SALSA_DUMP").is_ok() {
         println!("~~~ query_group");
-        println!("{}", output.to_string());
+        println!("{}", output);
         println!("~~~ query_group");
     }
 
-    output.into()
+    Ok(output)
 }
 
 struct SalsaAttr {
     name: String,
+    span: Span,
     tts: TokenStream,
 }
 
@@ -526,9 +708,11 @@ impl TryFrom<syn::Attribute> for SalsaAttr {
             return Err(attr);
         }
 
-        let name = attr.path.segments[1].ident.to_string();
+        let ident = &attr.path.segments[1].ident;
+        let name = ident.to_string();
+        let span = ident.span();
         let tts = attr.tts.into();
-        Ok(SalsaAttr { name, tts })
+        Ok(SalsaAttr { name, span, tts })
     }
 }
 
@@ -565,9 +749,31 @@ struct Query {
     keys: Vec<syn::Type>,
     value: syn::Type,
     invoke: Option<syn::Path>,
+    projection: Option<Projection>,
+    canonicalize: Option<syn::Path>,
+    validate: Option<syn::Path>,
+    recovery: Option<syn::Path>,
 }
 
 impl Query {
+    /// Collects the doc comments on the query method into a single
+    /// string, one line per `///` line.
+    fn description(&self) -> String {
+        let lines: Vec<String> = self
+            .attrs
+            .iter()
+            .filter_map(|attr| match attr.interpret_meta() {
+                Some(syn::Meta::NameValue(syn::MetaNameValue {
+                    ident,
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) if ident == "doc" => Some(lit.value().trim().to_string()),
+                _ => None,
+            })
+            .collect();
+        lines.join("\n").trim().to_string()
+    }
+
     fn invoke_tt(&self) -> proc_macro2::TokenStream {
         match &self.invoke {
             Some(i) => i.into_token_stream(),
@@ -576,6 +782,35 @@ impl Query {
     }
 }
 
+/// The arguments of `#[salsa::projection(of = parent, via = path::to::fn)]`:
+/// the query computes `fn(&parent(keys))`.
+#[derive(Debug)]
+struct Projection {
+    span: Span,
+    of: Ident,
+    via: Path,
+}
+
+impl Parse for Projection {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let span = input.cursor().span();
+        let key: Ident = input.parse()?;
+        if key != "of" {
+            return Err(Error::new(key.span(), "expected `of = <query>`"));
+        }
+        input.parse::<Token![=]>()?;
+        let of: Ident = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let key: Ident = input.parse()?;
+        if key != "via" {
+            return Err(Error::new(key.span(), "expected `via = <function>`"));
+        }
+        input.parse::<Token![=]>()?;
+        let via: Path = input.parse()?;
+        Ok(Projection { span, of, via })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum QueryStorage {
     Memoized,
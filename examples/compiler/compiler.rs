@@ -1,4 +1,5 @@
 pub trait CompilerDatabase: salsa::Database {
+    #[allow(dead_code)]
     fn interner(&self) -> &Interner;
 }
 
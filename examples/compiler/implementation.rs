@@ -22,6 +22,7 @@ pub struct DatabaseImpl {
     /// be ok: although the interner allocates internally when you
     /// intern something new, this never affects any previously
     /// interned values, so it's not going to affect query results.
+    #[allow(dead_code)]
     interner: Interner,
 }
 
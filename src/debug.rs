@@ -23,13 +23,41 @@ pub trait DebugQueryTable {
     fn is_constant(&self, key: Self::Key) -> bool;
 
     /// Get the (current) set of the entries in the query table.
+    ///
+    /// The order is deterministic: entries of input and derived
+    /// queries appear in the order in which their keys were stored
+    /// (an entry that is discarded and stored again moves to the end),
+    /// and entries of interned queries in intern-id order. Use
+    /// `entries_sorted_by_key` for an order that does not depend on
+    /// the history of the table.
     fn entries<C>(&self) -> C
     where
         C: FromIterator<TableEntry<Self::Key, Self::Value>>;
+
+    /// Invoke `op` on each entry in the query table, in the same order
+    /// as `entries`, and collect the results. Unlike `entries`, keys
+    /// and values are passed by reference rather than cloned, which
+    /// matters for queries with large values.
+    ///
+    /// `op` runs while the table is locked, so it must not invoke
+    /// queries on the database.
+    fn map_entries<R>(&self, op: impl FnMut(&Self::Key, Option<&Self::Value>) -> R) -> Vec<R>;
+
+    /// Like `entries`, but sorted by key, so that the result does not
+    /// depend on the order in which keys were used.
+    fn entries_sorted_by_key(&self) -> Vec<TableEntry<Self::Key, Self::Value>>
+    where
+        Self::Key: Ord,
+    {
+        let mut entries: Vec<_> = self.entries();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
 }
 
 /// An entry from a query table, for debugging and inspecting the table state.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct TableEntry<K, V> {
     /// key of the query
     pub key: K,
@@ -48,6 +76,45 @@ impl<K, V> TableEntry<K, V> {
     }
 }
 
+/// Statistics about the tables of an interned query, for debugging
+/// and for spotting pathological key types. See
+/// [`QueryTable::intern_stats`](../struct.QueryTable.html#method.intern_stats).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InternStats {
+    /// Number of keys that are currently interned.
+    pub len: usize,
+    /// Number of intern-ids that were freed by the GC and are
+    /// available for reuse.
+    pub free: usize,
+    /// Approximate memory used by the intern tables. This is a
+    /// shallow count: heap data owned by the keys is not included.
+    pub shallow_bytes: usize,
+    /// Number of times interning a key found an existing intern-id.
+    pub hits: usize,
+    /// Number of times interning a key allocated a new intern-id.
+    pub misses: usize,
+    /// Number of interned keys whose full 64-bit hash is identical to
+    /// the hash of some other interned key. Keys that merely land in
+    /// the same bucket of the table are not counted. A large number
+    /// usually indicates a poor `Hash` impl for the key type.
+    pub hash_collisions: usize,
+}
+
+/// Statistics about the executions of a derived query. See
+/// [`QueryTable::execution_stats`](../struct.QueryTable.html#method.execution_stats).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExecutionStats {
+    /// Number of times the query function has been executed.
+    pub executions: usize,
+    /// The largest number of threads that were executing the query
+    /// (for distinct keys) at the same time. Recursive executions on
+    /// one thread count once. A value of 1 means that the query never
+    /// ran in parallel with itself.
+    pub peak_concurrency: usize,
+}
+
 impl<DB, Q> DebugQueryTable for QueryTable<'_, DB, Q>
 where
     DB: plumbing::GetQueryTable<Q>,
@@ -57,7 +124,7 @@ where
     type Value = Q::Value;
 
     fn is_constant(&self, key: Q::Key) -> bool {
-        self.storage.is_constant(self.db, &key)
+        self.storage.is_constant(self.db, &Q::canonicalize_key(key))
     }
 
     fn entries<C>(&self) -> C
@@ -66,4 +133,8 @@ where
     {
         self.storage.entries(self.db)
     }
+
+    fn map_entries<R>(&self, op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R) -> Vec<R> {
+        self.storage.map_entries(self.db, op)
+    }
 }
@@ -1,23 +1,30 @@
+use crate::debug::ExecutionStats;
 use crate::debug::TableEntry;
 use crate::plumbing::CycleDetected;
 use crate::plumbing::DatabaseKey;
+use crate::plumbing::DerivedQueryStorageOps;
+use crate::plumbing::FetchError;
 use crate::plumbing::QueryFunction;
 use crate::plumbing::QueryStorageMassOps;
 use crate::plumbing::QueryStorageOps;
 use crate::runtime::ChangedAt;
+use crate::runtime::FxIndexMap;
 use crate::runtime::FxIndexSet;
+use crate::runtime::MemoMeta;
 use crate::runtime::Revision;
 use crate::runtime::Runtime;
 use crate::runtime::RuntimeId;
 use crate::runtime::StampedValue;
-use crate::{Database, DiscardIf, DiscardWhat, Event, EventKind, SweepStrategy};
+use crate::{Database, DiscardIf, DiscardWhat, Event, EventKind, Query, SweepStrategy};
 use log::{debug, info};
 use parking_lot::Mutex;
 use parking_lot::RwLock;
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
+use std::cell::RefCell;
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 
@@ -44,10 +51,26 @@ where
     DB: Database,
     MP: MemoizationPolicy<DB, Q>,
 {
-    map: RwLock<FxHashMap<Q::Key, QueryState<DB, Q>>>,
+    map: RwLock<FxIndexMap<Q::Key, QueryState<DB, Q>>>,
+    implementation: RwLock<Option<QueryImpl<DB, Q>>>,
+    /// Set once `register_impl` has been used, so that storages
+    /// without a registered implementation need not lock it.
+    has_implementation: AtomicBool,
+    mocks: RwLock<FxHashMap<Q::Key, StampedValue<Q::Value>>>,
+    /// Set once `mock` has been used, so that storages that are never
+    /// mocked need not lock `mocks`.
+    has_mocks: AtomicBool,
+    executions: AtomicUsize,
+    active_executions: AtomicUsize,
+    peak_executions: AtomicUsize,
     policy: PhantomData<MP>,
 }
 
+/// A query function registered at runtime via `register_impl`, which
+/// is used in place of `QueryFunction::execute`.
+type QueryImpl<DB, Q> =
+    Arc<dyn Fn(&DB, <Q as Query<DB>>::Key) -> <Q as Query<DB>>::Value + Send + Sync>;
+
 impl<DB, Q, MP> std::panic::RefUnwindSafe for DerivedStorage<DB, Q, MP>
 where
     Q: QueryFunction<DB>,
@@ -133,6 +156,10 @@ where
     }
 }
 
+/// Channels to notify the threads that are blocked waiting for an
+/// in-progress query to complete.
+type WaitingList<V> = Mutex<SmallVec<[Sender<StampedValue<V>>; 2]>>;
+
 /// Defines the "current state" of query's memoized results.
 enum QueryState<DB, Q>
 where
@@ -144,7 +171,7 @@ where
     /// indeeds a cycle.
     InProgress {
         id: RuntimeId,
-        waiting: Mutex<SmallVec<[Sender<StampedValue<Q::Value>>; 2]>>,
+        waiting: WaitingList<Q::Value>,
     },
 
     /// We have computed the query already, and here is the result.
@@ -163,10 +190,10 @@ where
         }
     }
 
-    fn value(&self) -> Option<Q::Value> {
+    fn value(&self) -> Option<&Q::Value> {
         match self {
             QueryState::InProgress { .. } => None,
-            QueryState::Memoized(memo) => memo.value.clone(),
+            QueryState::Memoized(memo) => memo.value.as_ref(),
         }
     }
 }
@@ -189,6 +216,10 @@ where
 
     /// The inputs that went into our query, if we are tracking them.
     inputs: MemoInputs<DB>,
+
+    /// Metadata attached by the query function via
+    /// `Runtime::attach_meta`.
+    meta: Option<MemoMeta>,
 }
 
 /// An insertion-order-preserving set of queries. Used to track the
@@ -208,11 +239,7 @@ pub(crate) enum MemoInputs<DB: Database> {
 
 impl<DB: Database> MemoInputs<DB> {
     fn is_constant(&self) -> bool {
-        if let MemoInputs::Constant = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, MemoInputs::Constant)
     }
 }
 
@@ -236,12 +263,74 @@ where
 {
     fn default() -> Self {
         DerivedStorage {
-            map: RwLock::new(FxHashMap::default()),
+            map: RwLock::new(FxIndexMap::default()),
+            implementation: RwLock::new(None),
+            has_implementation: AtomicBool::new(false),
+            mocks: RwLock::new(FxHashMap::default()),
+            has_mocks: AtomicBool::new(false),
+            executions: AtomicUsize::new(0),
+            active_executions: AtomicUsize::new(0),
+            peak_executions: AtomicUsize::new(0),
             policy: PhantomData,
         }
     }
 }
 
+thread_local! {
+    /// For each derived storage (by the address of its
+    /// `active_executions` counter), the number of executions of that
+    /// query currently on this thread's stack.
+    static EXECUTION_DEPTHS: RefCell<FxHashMap<usize, usize>> = RefCell::default();
+}
+
+/// Counts an execution of the query as active while it is alive
+/// (including while unwinding), updating the peak count. Only the
+/// outermost execution on each thread is counted, so that recursive
+/// queries do not report their recursion depth as concurrency.
+struct ActiveExecution<'me> {
+    active_executions: &'me AtomicUsize,
+    outermost: bool,
+}
+
+impl<'me> ActiveExecution<'me> {
+    fn new(active_executions: &'me AtomicUsize, peak_executions: &AtomicUsize) -> Self {
+        let outermost = Self::adjust_depth(active_executions, |depth| depth + 1) == 1;
+        if outermost {
+            let active = active_executions.fetch_add(1, Ordering::Relaxed) + 1;
+            peak_executions.fetch_max(active, Ordering::Relaxed);
+        }
+        ActiveExecution {
+            active_executions,
+            outermost,
+        }
+    }
+
+    /// Applies `op` to this thread's execution depth for the storage
+    /// that owns `active_executions`, returning the new depth.
+    fn adjust_depth(active_executions: &AtomicUsize, op: impl FnOnce(usize) -> usize) -> usize {
+        let storage = active_executions as *const AtomicUsize as usize;
+        EXECUTION_DEPTHS.with(|depths| {
+            let mut depths = depths.borrow_mut();
+            let depth = op(depths.get(&storage).copied().unwrap_or(0));
+            if depth == 0 {
+                depths.remove(&storage);
+            } else {
+                depths.insert(storage, depth);
+            }
+            depth
+        })
+    }
+}
+
+impl Drop for ActiveExecution<'_> {
+    fn drop(&mut self) {
+        Self::adjust_depth(self.active_executions, |depth| depth - 1);
+        if self.outermost {
+            self.active_executions.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Return value of `probe` helper.
 enum ProbeState<V, G> {
     UpToDate(Result<V, CycleDetected>),
@@ -276,6 +365,10 @@ where
             revision_now,
         );
 
+        if let Some(stamped_value) = self.mocked(key) {
+            return Ok(stamped_value);
+        }
+
         // First, do a check with a read-lock.
         match self.probe(
             db,
@@ -369,13 +462,23 @@ where
         let mut result = runtime.execute_query_implementation(db, database_key, || {
             info!("{:?}({:?}): executing query", Q::default(), key);
 
+            self.executions.fetch_add(1, Ordering::Relaxed);
+            let _active = ActiveExecution::new(&self.active_executions, &self.peak_executions);
+
             if !self.should_track_inputs(key) {
                 runtime.report_untracked_read();
             }
 
-            Q::execute(db, key.clone())
+            self.invoke(db, key)
         });
 
+        // Validate with the query back on the stack, so that a failing
+        // check reports it, but without tracking: whatever the check
+        // reads must not become a dependency of the value.
+        if cfg!(debug_assertions) {
+            runtime.execute_untracked(database_key, || Q::validate(db, key, &result.value));
+        }
+
         // We assume that query is side-effect free -- that is, does
         // not mutate the "inputs" to the query system. Sanity check
         // that assumption here, at least to the best of our ability.
@@ -388,10 +491,10 @@ where
         // If the new value is equal to the old one, then it didn't
         // really change, even if some of its inputs have. So we can
         // "backdate" its `changed_at` revision to be the same as the
-        // old value.
-        if let Some(old_memo) = &panic_guard.memo {
+        // old value. (Backdating is skipped in one-shot mode.)
+        if let Some(old_memo) = panic_guard.memo.as_ref().filter(|_| !runtime.is_one_shot()) {
             if let Some(old_value) = &old_memo.value {
-                if MP::memoized_value_eq(&old_value, &result.value) {
+                if MP::memoized_value_eq(old_value, &result.value) {
                     debug!(
                         "read_upgrade({:?}({:?})): value is equal, back-dating to {:?}",
                         Q::default(),
@@ -401,8 +504,13 @@ where
 
                     assert!(old_memo.changed_at <= result.changed_at.revision);
                     result.changed_at.revision = old_memo.changed_at;
+                } else {
+                    runtime.report_changed_value(revision_now, database_key);
                 }
             }
+        } else if panic_guard.memo.is_none() {
+            // Computed for the first time.
+            runtime.report_changed_value(revision_now, database_key);
         }
 
         let new_value = StampedValue {
@@ -450,6 +558,7 @@ where
             changed_at: result.changed_at.revision,
             verified_at: revision_now,
             inputs,
+            meta: result.meta,
         });
 
         panic_guard.proceed(&new_value);
@@ -472,8 +581,8 @@ where
     ///   (which does not depend on us) was already computing this
     ///   value; caller should re-acquire the lock and try again.
     /// - `ProbeState::StaleOrAbsent` if either (a) there is no memo
-    ///    for this key, (b) the memo has no value; or (c) the memo
-    ///    has not been verified at the current revision.
+    ///   for this key, (b) the memo has no value; or (c) the memo
+    ///   has not been verified at the current revision.
     ///
     /// Note that in all cases **except** for `StaleOrAbsent`, the lock on
     /// `map` will have been released.
@@ -487,7 +596,7 @@ where
         key: &Q::Key,
     ) -> ProbeState<StampedValue<Q::Value>, MapGuard>
     where
-        MapGuard: Deref<Target = FxHashMap<Q::Key, QueryState<DB, Q>>>,
+        MapGuard: Deref<Target = FxIndexMap<Q::Key, QueryState<DB, Q>>>,
     {
         match map.get(key) {
             Some(QueryState::InProgress { id, waiting }) => {
@@ -552,10 +661,10 @@ where
         runtime: &Runtime<DB>,
         database_key: &DB::DatabaseKey,
         other_id: RuntimeId,
-        waiting: &Mutex<SmallVec<[Sender<StampedValue<Q::Value>>; 2]>>,
+        waiting: &WaitingList<Q::Value>,
     ) -> Result<Receiver<StampedValue<Q::Value>>, CycleDetected> {
         if other_id == runtime.id() {
-            return Err(CycleDetected);
+            Err(CycleDetected)
         } else {
             if !runtime.try_block_on(database_key, other_id) {
                 return Err(CycleDetected);
@@ -578,6 +687,28 @@ where
     fn should_track_inputs(&self, key: &Q::Key) -> bool {
         MP::should_track_inputs(key)
     }
+
+    /// Computes the value for `key` with the implementation registered
+    /// by `register_impl`, if any, or else with `Q::execute`.
+    fn invoke(&self, db: &DB, key: &Q::Key) -> Q::Value {
+        if !self.has_implementation.load(Ordering::Acquire) {
+            return Q::execute(db, key.clone());
+        }
+
+        let implementation = self.implementation.read().clone();
+        match implementation {
+            Some(implementation) => implementation(db, key.clone()),
+            None => Q::execute(db, key.clone()),
+        }
+    }
+
+    /// Returns the value installed for `key` by `mock`, if any.
+    fn mocked(&self, key: &Q::Key) -> Option<StampedValue<Q::Value>> {
+        if !self.has_mocks.load(Ordering::Acquire) {
+            return None;
+        }
+        self.mocks.read().get(key).cloned()
+    }
 }
 
 struct PanicGuard<'db, DB, Q>
@@ -588,7 +719,7 @@ where
     database_key: &'db DB::DatabaseKey,
     key: &'db Q::Key,
     memo: Option<Memo<DB, Q>>,
-    map: &'db RwLock<FxHashMap<Q::Key, QueryState<DB, Q>>>,
+    map: &'db RwLock<FxIndexMap<Q::Key, QueryState<DB, Q>>>,
     runtime: &'db Runtime<DB>,
 }
 
@@ -598,7 +729,7 @@ where
     Q: QueryFunction<DB>,
 {
     fn new(
-        map: &'db RwLock<FxHashMap<Q::Key, QueryState<DB, Q>>>,
+        map: &'db RwLock<FxIndexMap<Q::Key, QueryState<DB, Q>>>,
         key: &'db Q::Key,
         memo: Option<Memo<DB, Q>>,
         database_key: &'db DB::DatabaseKey,
@@ -635,7 +766,7 @@ where
             // We had installed an `InProgress` marker, but we panicked before
             // it could be removed. At this point, we therefore "own" unique
             // access to our slot, so we can just remove the key.
-            None => write.remove(self.key),
+            None => write.shift_remove(self.key),
         };
 
         match old_value {
@@ -699,11 +830,26 @@ where
         db: &DB,
         key: &Q::Key,
         database_key: &DB::DatabaseKey,
-    ) -> Result<Q::Value, CycleDetected> {
-        let StampedValue { value, changed_at } = self.read(db, key, &database_key)?;
+    ) -> Result<Q::Value, FetchError> {
+        let runtime = db.salsa_runtime();
+        let StampedValue { value, changed_at } = match self.read(db, key, database_key) {
+            Ok(stamped_value) => stamped_value,
+            Err(CycleDetected) => {
+                let cycle = runtime.find_cycle(database_key);
+                match Q::recover(db, &cycle, key) {
+                    Some(value) => {
+                        // The fallback stands in for a value that is
+                        // still being computed, so the reader must not
+                        // be reused in later revisions.
+                        runtime.report_untracked_read();
+                        return Ok(value);
+                    }
+                    None => return Err(FetchError::Cycle),
+                }
+            }
+        };
 
-        db.salsa_runtime()
-            .report_query_read(database_key, changed_at);
+        runtime.report_query_read(database_key, changed_at);
 
         Ok(value)
     }
@@ -726,6 +872,10 @@ where
             revision_now,
         );
 
+        if let Some(stamped_value) = self.mocked(key) {
+            return stamped_value.changed_at.changed_since(revision);
+        }
+
         // Acquire read lock to start. In some of the arms below, we
         // drop this explicitly.
         let map = self.map.read();
@@ -812,7 +962,7 @@ where
                 // which will do that checking (and a bit more) --
                 // note that we skip the "pure read" part as we
                 // already know the result.
-                assert!(inputs.len() > 0);
+                assert!(!inputs.is_empty());
                 if memo.value.is_some() {
                     std::mem::drop(map);
                     return match self.read_upgrade(db, key, database_key, revision_now) {
@@ -883,7 +1033,7 @@ where
                         // We found this entry is out of date and
                         // nobody touch it in the meantime. Just
                         // remove it.
-                        map.remove(key);
+                        map.shift_remove(key);
                     } else {
                         // We found this entry is valid. Update the
                         // `verified_at` to reflect the current
@@ -925,11 +1075,142 @@ where
     {
         let map = self.map.read();
         map.iter()
-            .map(|(key, query_state)| TableEntry::new(key.clone(), query_state.value()))
+            .map(|(key, query_state)| TableEntry::new(key.clone(), query_state.value().cloned()))
+            .collect()
+    }
+
+    fn map_entries<R>(
+        &self,
+        _db: &DB,
+        mut op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R,
+    ) -> Vec<R> {
+        let map = self.map.read();
+        map.iter()
+            .map(|(key, query_state)| op(key, query_state.value()))
             .collect()
     }
 }
 
+impl<DB, Q, MP> DerivedQueryStorageOps<DB, Q> for DerivedStorage<DB, Q, MP>
+where
+    Q: QueryFunction<DB>,
+    DB: Database,
+    MP: MemoizationPolicy<DB, Q>,
+{
+    fn register_impl(
+        &self,
+        _db: &DB,
+        implementation: impl Fn(&DB, Q::Key) -> Q::Value + Send + Sync + 'static,
+    ) {
+        // Hold the map lock so that no value can be memoized between
+        // the check and the swap.
+        let map = self.map.write();
+        assert!(
+            map.is_empty(),
+            "cannot register an implementation for `{:?}` after it has been executed",
+            Q::default(),
+        );
+        *self.implementation.write() = Some(Arc::new(implementation));
+        self.has_implementation.store(true, Ordering::Release);
+    }
+
+    fn mock(&self, db: &DB, key: &Q::Key, database_key: &DB::DatabaseKey, value: Q::Value) {
+        db.salsa_runtime()
+            .with_incremented_revision(Some(database_key), false, |next_revision| {
+                let stamped_value = StampedValue {
+                    value,
+                    changed_at: ChangedAt {
+                        is_constant: false,
+                        revision: next_revision,
+                    },
+                };
+                self.mocks.write().insert(key.clone(), stamped_value);
+                self.has_mocks.store(true, Ordering::Release);
+            });
+    }
+
+    fn unmock(&self, db: &DB, key: &Q::Key, database_key: &DB::DatabaseKey) {
+        if self.mocked(key).is_none() {
+            return;
+        }
+
+        db.salsa_runtime()
+            .with_incremented_revision(Some(database_key), false, |_next_revision| {
+                self.mocks.write().remove(key);
+
+                // Discard any memo from before the mock was installed:
+                // it may validate as unchanged, but dependents have
+                // since observed the mocked value.
+                self.map.write().shift_remove(key);
+            });
+    }
+
+    fn fetch_if_cached(&self, db: &DB, key: &Q::Key) -> Option<Q::Value> {
+        let value = match self.mocked(key) {
+            Some(stamped_value) => Some(stamped_value.value),
+            None => self
+                .map
+                .read()
+                .get(key)
+                .and_then(|state| state.value().cloned()),
+        };
+
+        // The value was not validated against the current revision,
+        // so a query that peeks must be re-executed in every revision.
+        db.salsa_runtime().report_untracked_read();
+
+        value
+    }
+
+    fn meta(&self, _db: &DB, key: &Q::Key) -> Option<MemoMeta> {
+        match self.map.read().get(key) {
+            Some(QueryState::Memoized(memo)) => memo.meta.clone(),
+            _ => None,
+        }
+    }
+
+    fn execution_stats(&self, _db: &DB) -> ExecutionStats {
+        ExecutionStats {
+            executions: self.executions.load(Ordering::Relaxed),
+            peak_concurrency: self.peak_executions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn verify(&self, db: &DB, key: &Q::Key, database_key: &DB::DatabaseKey) {
+        let runtime = db.salsa_runtime();
+        let revision_now = runtime.current_revision();
+
+        // Only memos that are known to be up to date (and whose
+        // inputs are all known) must match a fresh execution.
+        let memoized_value = match self.map.read().get(key) {
+            Some(QueryState::Memoized(memo))
+                if self.mocked(key).is_none()
+                    && (memo.verified_at == revision_now || memo.inputs.is_constant())
+                    && !matches!(memo.inputs, MemoInputs::Untracked) =>
+            {
+                memo.value.clone()
+            }
+            _ => None,
+        };
+        let memoized_value = match memoized_value {
+            Some(value) => value,
+            None => return,
+        };
+
+        let result =
+            runtime.execute_query_implementation(db, database_key, || self.invoke(db, key));
+
+        assert!(
+            MP::memoized_value_eq(&memoized_value, &result.value),
+            "`{:?}({:?})` is not deterministic: memoized value `{:?}`, recomputed value `{:?}`",
+            Q::default(),
+            key,
+            memoized_value,
+            result.value,
+        );
+    }
+}
+
 impl<DB, Q, MP> QueryStorageMassOps<DB> for DerivedStorage<DB, Q, MP>
 where
     Q: QueryFunction<DB>,
@@ -968,10 +1249,7 @@ where
                     // revision, we might wind up re-executing the
                     // query later in the revision and getting a
                     // distinct result.
-                    let is_volatile = match memo.inputs {
-                        MemoInputs::Untracked => true,
-                        _ => false,
-                    };
+                    let is_volatile = matches!(memo.inputs, MemoInputs::Untracked);
 
                     // Since we don't acquire a query lock in this
                     // method, it *is* possible for the revision to
@@ -1036,7 +1314,7 @@ where
         let is_constant = match &mut self.inputs {
             // We can't validate values that had untracked inputs; just have to
             // re-execute.
-            MemoInputs::Untracked { .. } => {
+            MemoInputs::Untracked => {
                 return None;
             }
 
@@ -1055,8 +1333,7 @@ where
             MemoInputs::Tracked { inputs } => {
                 let changed_input = inputs
                     .iter()
-                    .filter(|input| input.maybe_changed_since(db, verified_at))
-                    .next();
+                    .find(|input| input.maybe_changed_since(db, verified_at));
 
                 if let Some(input) = changed_input {
                     debug!(
@@ -1092,10 +1369,7 @@ where
         );
 
         if self.verified_at == revision_now {
-            let is_constant = match self.inputs {
-                MemoInputs::Constant => true,
-                _ => false,
-            };
+            let is_constant = matches!(self.inputs, MemoInputs::Constant);
 
             return Some(StampedValue {
                 changed_at: ChangedAt {
@@ -0,0 +1,106 @@
+use crate::plumbing::GetQueryTable;
+use crate::plumbing::QueryStorageOps;
+use crate::Database;
+use crate::Query;
+use crate::Revision;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+
+/// A query key bundled with the type of its query: a reference to
+/// some value that can be fetched from the database. This lets APIs
+/// accept "something fetchable" without naming the query that
+/// produces it.
+pub struct QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    key: Q::Key,
+}
+
+impl<DB, Q> QueryHandle<DB, Q>
+where
+    DB: GetQueryTable<Q>,
+    Q: Query<DB>,
+{
+    /// Creates a handle for the given key of the query `Q`.
+    pub fn new(key: Q::Key) -> Self {
+        QueryHandle { key }
+    }
+
+    /// The key of the query.
+    pub fn key(&self) -> &Q::Key {
+        &self.key
+    }
+
+    /// Fetches the value of the query; equivalent to
+    /// `db.query(Q::default()).get(key)`.
+    pub fn fetch(&self, db: &DB) -> Q::Value {
+        db.query(Q::default()).get(self.key.clone())
+    }
+
+    /// True if the value of the query **may** have changed since the
+    /// given revision (see `QueryStorageOps::maybe_changed_since`).
+    pub fn maybe_changed_since(&self, db: &DB, revision: Revision) -> bool {
+        let key = Q::canonicalize_key(self.key.clone());
+        let table = db.query(Q::default());
+        let database_key = <DB as GetQueryTable<Q>>::database_key(db, key.clone());
+        table
+            .storage
+            .maybe_changed_since(db, revision, &key, &database_key)
+    }
+
+    /// Returns the database key that identifies this query and key.
+    pub fn database_key(&self, db: &DB) -> DB::DatabaseKey {
+        <DB as GetQueryTable<Q>>::database_key(db, Q::canonicalize_key(self.key.clone()))
+    }
+}
+
+impl<DB, Q> Clone for QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    fn clone(&self) -> Self {
+        QueryHandle {
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<DB, Q> PartialEq for QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<DB, Q> Eq for QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+}
+
+impl<DB, Q> Hash for QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl<DB, Q> Debug for QueryHandle<DB, Q>
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{:?}({:?})", Q::default(), self.key)
+    }
+}
@@ -1,9 +1,10 @@
 use crate::debug::TableEntry;
-use crate::plumbing::CycleDetected;
+use crate::plumbing::FetchError;
 use crate::plumbing::InputQueryStorageOps;
 use crate::plumbing::QueryStorageMassOps;
 use crate::plumbing::QueryStorageOps;
 use crate::runtime::ChangedAt;
+use crate::runtime::FxIndexMap;
 use crate::runtime::Revision;
 use crate::runtime::StampedValue;
 use crate::Database;
@@ -11,10 +12,10 @@ use crate::Event;
 use crate::EventKind;
 use crate::Query;
 use crate::SweepStrategy;
+use indexmap::map::Entry;
 use log::debug;
 use parking_lot::RwLock;
-use rustc_hash::FxHashMap;
-use std::collections::hash_map::Entry;
+use rustc_hash::FxHashSet;
 
 /// Input queries store the result plus a list of the other queries
 /// that they invoked. This means we can avoid recomputing them when
@@ -24,7 +25,11 @@ where
     Q: Query<DB>,
     DB: Database,
 {
-    map: RwLock<FxHashMap<Q::Key, StampedValue<Q::Value>>>,
+    map: RwLock<FxIndexMap<Q::Key, StampedValue<Q::Value>>>,
+
+    /// The revision in which keys were last removed by `retain`;
+    /// absent keys are considered to have changed then.
+    removed_at: RwLock<Revision>,
 }
 
 impl<DB, Q> std::panic::RefUnwindSafe for InputStorage<DB, Q>
@@ -43,7 +48,8 @@ where
 {
     fn default() -> Self {
         InputStorage {
-            map: RwLock::new(FxHashMap::default()),
+            map: RwLock::new(FxIndexMap::default()),
+            removed_at: RwLock::new(Revision::ZERO),
         }
     }
 }
@@ -55,20 +61,17 @@ where
     Q: Query<DB>,
     DB: Database,
 {
-    fn read<'q>(
+    fn read(
         &self,
-        _db: &'q DB,
+        _db: &DB,
         key: &Q::Key,
         _database_key: &DB::DatabaseKey,
-    ) -> Result<StampedValue<Q::Value>, CycleDetected> {
-        {
-            let map_read = self.map.read();
-            if let Some(value) = map_read.get(key) {
-                return Ok(value.clone());
-            }
+    ) -> Result<StampedValue<Q::Value>, FetchError> {
+        let map_read = self.map.read();
+        match map_read.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => Err(FetchError::UnsetInput),
         }
-
-        panic!("no value set for {:?}({:?})", Q::default(), key)
     }
 
     fn set_common(
@@ -89,46 +92,65 @@ where
         // need to read from this input. Therefore, we wait to acquire
         // the lock on `map` until we also hold the global query write
         // lock.
-        db.salsa_runtime().with_incremented_revision(|next_revision| {
+        let runtime = db.salsa_runtime();
+        runtime.with_incremented_revision(Some(database_key), is_constant.0, |next_revision| {
             let mut map = self.map.write();
-
-            db.salsa_event(|| Event {
-                runtime_id: db.salsa_runtime().id(),
-                kind: EventKind::WillChangeInputValue {
-                    database_key: database_key.clone(),
+            self.insert(
+                db,
+                &mut map,
+                key,
+                database_key,
+                value,
+                ChangedAt {
+                    is_constant: is_constant.0,
+                    revision: next_revision,
                 },
-            });
+            );
+        });
+    }
 
-            // Do this *after* we acquire the lock, so that we are not
-            // racing with somebody else to modify this same cell.
-            // (Otherwise, someone else might write a *newer* revision
-            // into the same cell while we block on the lock.)
-            let changed_at = ChangedAt {
-                is_constant: is_constant.0,
-                revision: next_revision,
-            };
+    /// Stores `value` into `map`, which must have been locked while
+    /// holding the global query write lock.
+    fn insert(
+        &self,
+        db: &DB,
+        map: &mut FxIndexMap<Q::Key, StampedValue<Q::Value>>,
+        key: Q::Key,
+        database_key: &DB::DatabaseKey,
+        value: Q::Value,
+        changed_at: ChangedAt,
+    ) {
+        db.salsa_event(|| Event {
+            runtime_id: db.salsa_runtime().id(),
+            kind: EventKind::WillChangeInputValue {
+                database_key: database_key.clone(),
+            },
+        });
 
-            let stamped_value = StampedValue { value, changed_at };
+        // Do this *after* we acquire the lock, so that we are not
+        // racing with somebody else to modify this same cell.
+        // (Otherwise, someone else might write a *newer* revision
+        // into the same cell while we block on the lock.)
+        let stamped_value = StampedValue { value, changed_at };
 
-            match map.entry(key) {
-                Entry::Occupied(mut entry) => {
-                    assert!(
-                        !entry.get().changed_at.is_constant,
-                        "modifying `{:?}({:?})`, which was previously marked as constant (old value `{:?}`, new value `{:?}`)",
-                        Q::default(),
-                        entry.key(),
-                        entry.get().value,
-                        stamped_value.value,
-                    );
+        match map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                assert!(
+                    !entry.get().changed_at.is_constant,
+                    "modifying `{:?}({:?})`, which was previously marked as constant (old value `{:?}`, new value `{:?}`)",
+                    Q::default(),
+                    entry.key(),
+                    entry.get().value,
+                    stamped_value.value,
+                );
 
-                    entry.insert(stamped_value);
-                }
+                entry.insert(stamped_value);
+            }
 
-                Entry::Vacant(entry) => {
-                    entry.insert(stamped_value);
-                }
+            Entry::Vacant(entry) => {
+                entry.insert(stamped_value);
             }
-        });
+        }
     }
 }
 
@@ -142,13 +164,22 @@ where
         db: &DB,
         key: &Q::Key,
         database_key: &DB::DatabaseKey,
-    ) -> Result<Q::Value, CycleDetected> {
-        let StampedValue { value, changed_at } = self.read(db, key, &database_key)?;
+    ) -> Result<Q::Value, FetchError> {
+        let result = self.read(db, key, database_key);
 
+        // Report the read even if the input is unset, so that a query
+        // which observed the error is re-executed once it is set.
+        let changed_at = match &result {
+            Ok(stamped_value) => stamped_value.changed_at,
+            Err(_) => ChangedAt {
+                is_constant: false,
+                revision: Revision::ZERO,
+            },
+        };
         db.salsa_runtime()
             .report_query_read(database_key, changed_at);
 
-        Ok(value)
+        result.map(|stamped_value| stamped_value.value)
     }
 
     fn maybe_changed_since(
@@ -170,9 +201,9 @@ where
             map_read
                 .get(key)
                 .map(|v| v.changed_at)
-                .unwrap_or(ChangedAt {
+                .unwrap_or_else(|| ChangedAt {
                     is_constant: false,
-                    revision: Revision::ZERO,
+                    revision: *self.removed_at.read(),
                 })
         };
 
@@ -205,6 +236,17 @@ where
             })
             .collect()
     }
+
+    fn map_entries<R>(
+        &self,
+        _db: &DB,
+        mut op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R,
+    ) -> Vec<R> {
+        let map = self.map.read();
+        map.iter()
+            .map(|(key, stamped_value)| op(key, Some(&stamped_value.value)))
+            .collect()
+    }
 }
 
 impl<DB, Q> QueryStorageMassOps<DB> for InputStorage<DB, Q>
@@ -231,4 +273,91 @@ where
 
         self.set_common(db, key, database_key, value, IsConstant(true))
     }
+
+    fn set_many(
+        &self,
+        db: &DB,
+        values: impl IntoIterator<Item = (Q::Key, DB::DatabaseKey, Q::Value)>,
+    ) {
+        let values: Vec<_> = values.into_iter().collect();
+        let first_database_key = match values.first() {
+            Some((_, database_key, _)) => database_key.clone(),
+            None => return,
+        };
+
+        // Check every key before modifying anything, so that a panic
+        // leaves the storage untouched. Inputs can only be set through
+        // `query_mut`, so nothing changes in the meantime.
+        {
+            let map = self.map.read();
+            for (key, _, value) in &values {
+                if let Some(stamped_value) = map.get(key) {
+                    assert!(
+                        !stamped_value.changed_at.is_constant,
+                        "modifying `{:?}({:?})`, which was previously marked as constant (old value `{:?}`, new value `{:?}`)",
+                        Q::default(),
+                        key,
+                        stamped_value.value,
+                        value,
+                    );
+                }
+            }
+        }
+
+        // As in `set_common`, the map is only locked once we hold the
+        // global query write lock.
+        let runtime = db.salsa_runtime();
+        runtime.with_incremented_revision(Some(&first_database_key), false, |next_revision| {
+            let mut map = self.map.write();
+            for (key, database_key, value) in values {
+                log::debug!("{:?}({:?}) = {:?}", Q::default(), key, value);
+
+                self.insert(
+                    db,
+                    &mut map,
+                    key,
+                    &database_key,
+                    value,
+                    ChangedAt {
+                        is_constant: false,
+                        revision: next_revision,
+                    },
+                );
+            }
+        });
+    }
+
+    fn retain(&self, db: &DB, mut keep: impl FnMut(&Q::Key) -> bool) {
+        // Decide what to remove before modifying anything, so that a
+        // panic leaves the storage untouched. Inputs can only be set
+        // through `query_mut`, so nothing changes in the meantime.
+        let removed: FxHashSet<Q::Key> = {
+            let map = self.map.read();
+            map.iter()
+                .filter(|(key, _)| !keep(key))
+                .map(|(key, stamped_value)| {
+                    assert!(
+                        !stamped_value.changed_at.is_constant,
+                        "removing `{:?}({:?})`, which was previously marked as constant",
+                        Q::default(),
+                        key,
+                    );
+                    key.clone()
+                })
+                .collect()
+        };
+        if removed.is_empty() {
+            return;
+        }
+
+        let runtime = db.salsa_runtime();
+        runtime.with_incremented_revision(None, false, |next_revision| {
+            let mut map = self.map.write();
+            map.retain(|key, _| !removed.contains(key));
+            map.shrink_to_fit();
+
+            log::debug!("{:?}: removed {} inputs", Q::default(), removed.len());
+            *self.removed_at.write() = next_revision;
+        });
+    }
 }
@@ -1,19 +1,26 @@
+use crate::debug::InternStats;
 use crate::debug::TableEntry;
 use crate::intern_id::InternId;
-use crate::plumbing::CycleDetected;
+use crate::plumbing::FetchError;
 use crate::plumbing::HasQueryGroup;
+use crate::plumbing::InternedQueryStorageOps;
+use crate::plumbing::LookupQueryStorageOps;
 use crate::plumbing::QueryStorageMassOps;
 use crate::plumbing::QueryStorageOps;
 use crate::runtime::ChangedAt;
+use crate::runtime::FxIndexMap;
 use crate::runtime::Revision;
 use crate::runtime::StampedValue;
 use crate::Query;
 use crate::{Database, DiscardIf, SweepStrategy};
 use parking_lot::RwLock;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::hash_map::Entry;
 use std::convert::From;
-use std::hash::Hash;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// Handles storage where the value is 'derived' by executing a
 /// function (in contrast to "inputs").
@@ -56,6 +63,13 @@ struct InternTables<K> {
 
     /// Index of the first free intern-index, if any.
     first_free: Option<InternId>,
+
+    /// Number of times that interning found an existing intern-index.
+    /// (Atomic so that it can be updated with only a read lock held.)
+    hits: AtomicUsize,
+
+    /// Number of times that interning allocated a new intern-index.
+    misses: AtomicUsize,
 }
 
 /// Trait implemented for the "key" that results from a
@@ -154,42 +168,47 @@ where
             map: Default::default(),
             values: Default::default(),
             first_free: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
         }
     }
 }
 
-impl<DB, Q> InternedStorage<DB, Q>
+impl<K> InternTables<K>
 where
-    Q: Query<DB>,
-    Q::Key: Eq + Hash + Clone,
-    Q::Value: InternKey,
-    DB: Database,
+    K: Debug + Eq + Hash + Clone,
 {
-    fn intern_index(&self, db: &DB, key: &Q::Key) -> StampedValue<InternId> {
-        if let Some(i) = self.intern_check(db, key) {
-            return i;
-        }
-
-        let owned_key1 = key.to_owned();
-        let owned_key2 = owned_key1.clone();
-        let revision_now = db.salsa_runtime().current_revision();
+    /// Iterates over the interned keys in intern-id order.
+    fn iter(&self) -> impl Iterator<Item = (&K, InternId)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| match value {
+                InternValue::Present { value, .. } => Some((value, InternId::from(index))),
+                InternValue::Free { .. } => None,
+            })
+    }
 
-        let mut tables = self.tables.write();
-        let tables = &mut *tables;
-        let entry = match tables.map.entry(owned_key1) {
+    /// Interns `key`, allocating a fresh intern-index if it has not
+    /// been seen before. Must be invoked with the write lock held.
+    fn intern(&mut self, key: K, revision_now: Revision) -> StampedValue<InternId> {
+        let owned_key2 = key.clone();
+        let entry = match self.map.entry(key) {
             Entry::Vacant(entry) => entry,
             Entry::Occupied(entry) => {
-                // Somebody inserted this key while we were waiting
-                // for the write lock.
+                // Either somebody inserted this key while we were
+                // waiting for the write lock, or (when interning a
+                // batch) the key was already present.
                 let index = *entry.get();
-                match &tables.values[index.as_usize()] {
+                match &mut self.values[index.as_usize()] {
                     InternValue::Present {
                         value,
                         interned_at,
                         accessed_at,
                     } => {
                         debug_assert_eq!(owned_key2, *value);
-                        debug_assert_eq!(*accessed_at, revision_now);
+                        *accessed_at = revision_now;
+                        self.hits.fetch_add(1, Ordering::Relaxed);
                         return StampedValue {
                             value: index,
                             changed_at: ChangedAt {
@@ -200,16 +219,16 @@ where
                     }
 
                     InternValue::Free { .. } => {
-                        panic!("key {:?} should be present but is not", key,);
+                        panic!("key {:?} should be present but is not", owned_key2);
                     }
                 }
             }
         };
 
-        let index = match tables.first_free {
+        let index = match self.first_free {
             None => {
-                let index = InternId::from(tables.values.len());
-                tables.values.push(InternValue::Present {
+                let index = InternId::from(self.values.len());
+                self.values.push(InternValue::Present {
                     value: owned_key2,
                     interned_at: revision_now,
                     accessed_at: revision_now,
@@ -218,7 +237,7 @@ where
             }
 
             Some(i) => {
-                let next_free = match &tables.values[i.as_usize()] {
+                let next_free = match &self.values[i.as_usize()] {
                     InternValue::Free { next } => *next,
                     InternValue::Present { value, .. } => {
                         panic!(
@@ -228,17 +247,18 @@ where
                     }
                 };
 
-                tables.values[i.as_usize()] = InternValue::Present {
+                self.values[i.as_usize()] = InternValue::Present {
                     value: owned_key2,
                     interned_at: revision_now,
                     accessed_at: revision_now,
                 };
-                tables.first_free = next_free;
+                self.first_free = next_free;
                 i
             }
         };
 
         entry.insert(index);
+        self.misses.fetch_add(1, Ordering::Relaxed);
 
         StampedValue {
             value: index,
@@ -249,7 +269,78 @@ where
         }
     }
 
-    fn intern_check(&self, db: &DB, key: &Q::Key) -> Option<StampedValue<InternId>> {
+    fn stats(&self) -> InternStats {
+        let hasher = self.map.hasher();
+        let mut hashes = FxHashSet::default();
+        let hash_collisions = self
+            .map
+            .keys()
+            .filter(|key| {
+                let mut state = hasher.build_hasher();
+                key.hash(&mut state);
+                !hashes.insert(state.finish())
+            })
+            .count();
+
+        let free = self.values.len() - self.map.len();
+        let shallow_bytes = self.values.capacity() * mem::size_of::<InternValue<K>>()
+            + self.map.capacity() * mem::size_of::<(K, InternId)>();
+
+        InternStats {
+            len: self.map.len(),
+            free,
+            shallow_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            hash_collisions,
+        }
+    }
+}
+
+struct CountHit(bool);
+
+impl<DB, Q> InternedStorage<DB, Q>
+where
+    Q: Query<DB>,
+    Q::Key: Eq + Hash + Clone,
+    Q::Value: InternKey,
+    DB: Database,
+{
+    fn intern_index(&self, db: &DB, key: &Q::Key) -> StampedValue<InternId> {
+        if let Some(i) = self.intern_check(db, key, CountHit(true)) {
+            return i;
+        }
+
+        let revision_now = db.salsa_runtime().current_revision();
+
+        let mut tables = self.tables.write();
+        tables.intern(key.to_owned(), revision_now)
+    }
+
+    /// Interns each of `keys`, acquiring the write lock on the intern
+    /// tables only once for the entire batch.
+    fn intern_indices(
+        &self,
+        db: &DB,
+        keys: impl IntoIterator<Item = Q::Key>,
+    ) -> Vec<StampedValue<InternId>> {
+        let revision_now = db.salsa_runtime().current_revision();
+
+        let mut tables = self.tables.write();
+        keys.into_iter()
+            .map(|key| tables.intern(key, revision_now))
+            .collect()
+    }
+
+    /// Returns the intern-index of `key`, if it has been interned,
+    /// updating its `accessed_at` time. If `count_hit` is true, the
+    /// lookup is counted as an interning hit in the stats.
+    fn intern_check(
+        &self,
+        db: &DB,
+        key: &Q::Key,
+        count_hit: CountHit,
+    ) -> Option<StampedValue<InternId>> {
         let revision_now = db.salsa_runtime().current_revision();
 
         // First,
@@ -263,6 +354,9 @@ where
                     ..
                 } => {
                     if *accessed_at == revision_now {
+                        if count_hit.0 {
+                            tables.hits.fetch_add(1, Ordering::Relaxed);
+                        }
                         return Some(StampedValue {
                             value: index,
                             changed_at: ChangedAt {
@@ -284,6 +378,7 @@ where
 
         // Next,
         let mut tables = self.tables.write();
+        let tables = &mut *tables;
         let &index = tables.map.get(key)?;
         match &mut tables.values[index.as_usize()] {
             InternValue::Present {
@@ -292,14 +387,17 @@ where
                 ..
             } => {
                 *accessed_at = revision_now;
+                if count_hit.0 {
+                    tables.hits.fetch_add(1, Ordering::Relaxed);
+                }
 
-                return Some(StampedValue {
+                Some(StampedValue {
                     value: index,
                     changed_at: ChangedAt {
                         is_constant: false,
                         revision: *interned_at,
                     },
-                });
+                })
             }
 
             InternValue::Free { .. } => {
@@ -364,13 +462,13 @@ where
             } => {
                 *accessed_at = revision_now;
 
-                return StampedValue {
+                StampedValue {
                     value: op(value),
                     changed_at: ChangedAt {
                         is_constant: false,
                         revision: *interned_at,
                     },
-                };
+                }
             }
 
             InternValue::Free { .. } => panic!(
@@ -393,7 +491,7 @@ where
         db: &DB,
         key: &Q::Key,
         database_key: &DB::DatabaseKey,
-    ) -> Result<Q::Value, CycleDetected> {
+    ) -> Result<Q::Value, FetchError> {
         let StampedValue { value, changed_at } = self.intern_index(db, key);
 
         db.salsa_runtime()
@@ -409,7 +507,7 @@ where
         key: &Q::Key,
         _database_key: &DB::DatabaseKey,
     ) -> bool {
-        match self.intern_check(db, key) {
+        match self.intern_check(db, key, CountHit(false)) {
             Some(StampedValue {
                 value: _,
                 changed_at,
@@ -428,13 +526,24 @@ where
     {
         let tables = self.tables.read();
         tables
-            .map
             .iter()
             .map(|(key, index)| {
-                TableEntry::new(key.clone(), Some(<Q::Value>::from_intern_id(*index)))
+                TableEntry::new(key.clone(), Some(<Q::Value>::from_intern_id(index)))
             })
             .collect()
     }
+
+    fn map_entries<R>(
+        &self,
+        _db: &DB,
+        mut op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R,
+    ) -> Vec<R> {
+        let tables = self.tables.read();
+        tables
+            .iter()
+            .map(|(key, index)| op(key, Some(&<Q::Value>::from_intern_id(index))))
+            .collect()
+    }
 }
 
 impl<DB, Q> QueryStorageMassOps<DB> for InternedStorage<DB, Q>
@@ -450,6 +559,7 @@ where
             map,
             values,
             first_free,
+            ..
         } = &mut *tables;
         map.retain(|key, intern_index| {
             let discard = match strategy.discard_if {
@@ -489,6 +599,47 @@ where
     }
 }
 
+impl<DB, Q> InternedQueryStorageOps<DB, Q> for InternedStorage<DB, Q>
+where
+    Q: Query<DB>,
+    Q::Value: InternKey,
+    DB: Database,
+{
+    fn intern_many(
+        &self,
+        db: &DB,
+        keys: impl IntoIterator<Item = (Q::Key, DB::DatabaseKey)>,
+    ) -> Vec<Q::Value> {
+        // Deduplicate the keys (in order of first occurrence), so that
+        // each distinct key is interned, and its read reported, once.
+        let mut distinct = FxIndexMap::default();
+        let positions: Vec<usize> = keys
+            .into_iter()
+            .map(|(key, database_key)| distinct.insert_full(key, database_key).0)
+            .collect();
+        let (keys, database_keys): (Vec<_>, Vec<_>) = distinct.into_iter().unzip();
+        let indices = self.intern_indices(db, keys);
+
+        let runtime = db.salsa_runtime();
+        let ids: Vec<InternId> = indices
+            .into_iter()
+            .zip(&database_keys)
+            .map(|(StampedValue { value, changed_at }, database_key)| {
+                runtime.report_query_read(database_key, changed_at);
+                value
+            })
+            .collect();
+        positions
+            .into_iter()
+            .map(|position| <Q::Value>::from_intern_id(ids[position]))
+            .collect()
+    }
+
+    fn stats(&self, _db: &DB) -> InternStats {
+        self.tables.read().stats()
+    }
+}
+
 impl<DB, Q, IQ> QueryStorageOps<DB, Q> for LookupInternedStorage<DB, Q, IQ>
 where
     Q: Query<DB>,
@@ -510,7 +661,7 @@ where
         db: &DB,
         key: &Q::Key,
         database_key: &DB::DatabaseKey,
-    ) -> Result<Q::Value, CycleDetected> {
+    ) -> Result<Q::Value, FetchError> {
         let index = key.as_intern_id();
 
         let group_storage = <DB as HasQueryGroup<Q::Group>>::group_storage(db);
@@ -575,11 +726,22 @@ where
         let interned_storage = IQ::query_storage(group_storage);
         let tables = interned_storage.tables.read();
         tables
-            .map
             .iter()
-            .map(|(key, index)| {
-                TableEntry::new(<Q::Key>::from_intern_id(*index), Some(key.clone()))
-            })
+            .map(|(key, index)| TableEntry::new(<Q::Key>::from_intern_id(index), Some(key.clone())))
+            .collect()
+    }
+
+    fn map_entries<R>(
+        &self,
+        db: &DB,
+        mut op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R,
+    ) -> Vec<R> {
+        let group_storage = <DB as HasQueryGroup<Q::Group>>::group_storage(db);
+        let interned_storage = IQ::query_storage(group_storage);
+        let tables = interned_storage.tables.read();
+        tables
+            .iter()
+            .map(|(key, index)| op(&<Q::Key>::from_intern_id(index), Some(key)))
             .collect()
     }
 }
@@ -601,3 +763,53 @@ where
 {
     fn sweep(&self, _db: &DB, _strategy: SweepStrategy) {}
 }
+
+impl<DB, Q, IQ> LookupQueryStorageOps<DB, Q> for LookupInternedStorage<DB, Q, IQ>
+where
+    Q: Query<DB>,
+    Q::Key: InternKey,
+    Q::Value: Eq + Hash,
+    IQ: Query<
+        DB,
+        Key = Q::Value,
+        Value = Q::Key,
+        Storage = InternedStorage<DB, IQ>,
+        Group = Q::Group,
+        GroupStorage = Q::GroupStorage,
+        GroupKey = Q::GroupKey,
+    >,
+    DB: Database + HasQueryGroup<Q::Group>,
+{
+    fn with_value<R>(
+        &self,
+        db: &DB,
+        key: &Q::Key,
+        database_key: &DB::DatabaseKey,
+        op: impl FnOnce(&Q::Value) -> R,
+    ) -> R {
+        let index = key.as_intern_id();
+
+        let group_storage = <DB as HasQueryGroup<Q::Group>>::group_storage(db);
+        let interned_storage = IQ::query_storage(group_storage);
+
+        // Update the `accessed_at` time first, so that `op` is only
+        // ever invoked under the read lock.
+        let StampedValue {
+            value: (),
+            changed_at,
+        } = interned_storage.lookup_value(db, index, |_| ());
+
+        db.salsa_runtime()
+            .report_query_read(database_key, changed_at);
+
+        let tables = interned_storage.tables.read();
+        match &tables.values[index.as_usize()] {
+            InternValue::Present { value, .. } => op(value),
+            InternValue::Free { .. } => panic!(
+                "interned key `{:?}({})` has been garbage collected",
+                Q::default(),
+                index.as_usize(),
+            ),
+        }
+    }
+}
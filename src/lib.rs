@@ -9,6 +9,7 @@
 //! from previous invocations as appropriate.
 
 mod derived;
+mod handle;
 mod input;
 mod intern_id;
 mod interned;
@@ -19,19 +20,31 @@ pub mod debug;
 /// and are exempt from the SemVer guarantees.
 #[doc(hidden)]
 pub mod plumbing;
+pub mod prelude;
 
-use crate::plumbing::CycleDetected;
+use crate::plumbing::DerivedQueryStorageOps;
+use crate::plumbing::FetchError;
 use crate::plumbing::InputQueryStorageOps;
+use crate::plumbing::InternedQueryStorageOps;
+use crate::plumbing::LookupQueryStorageOps;
 use crate::plumbing::QueryStorageMassOps;
 use crate::plumbing::QueryStorageOps;
 use derive_new::new;
 use std::fmt::{self, Debug};
 use std::hash::Hash;
 
+pub use crate::handle::QueryHandle;
 pub use crate::intern_id::InternId;
 pub use crate::interned::InternKey;
+pub use crate::runtime::CancellationToken;
+pub use crate::runtime::DatabaseId;
+pub use crate::runtime::FreezeGuard;
+pub use crate::runtime::Revision;
+pub use crate::runtime::RevisionRecord;
 pub use crate::runtime::Runtime;
 pub use crate::runtime::RuntimeId;
+pub use crate::runtime::SnapshotInfo;
+pub use crate::runtime::StalledWrite;
 
 /// The base trait which your "query context" must implement. Gives
 /// access to the salsa runtime, which you must embed into your query
@@ -208,32 +221,22 @@ impl<DB: Database> fmt::Debug for EventKind<DB> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 enum DiscardIf {
+    #[default]
     Never,
     Outdated,
     Always,
 }
 
-impl Default for DiscardIf {
-    fn default() -> DiscardIf {
-        DiscardIf::Never
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 enum DiscardWhat {
+    #[default]
     Nothing,
     Values,
     Everything,
 }
 
-impl Default for DiscardWhat {
-    fn default() -> DiscardWhat {
-        DiscardWhat::Nothing
-    }
-}
-
 /// The sweep strategy controls what data we will keep/discard when we
 /// do a GC-sweep. The default (`SweepStrategy::default`) is a no-op,
 /// use `SweepStrategy::discard_outdated` constructor or `discard_*`
@@ -386,6 +389,28 @@ where
     pub fn new(db: DB) -> Self {
         Snapshot { db }
     }
+
+    /// Returns the revision this snapshot observes. While the
+    /// snapshot is live no new revision can be created, so this never
+    /// changes; it is useful for tagging results computed from the
+    /// snapshot.
+    pub fn revision(&self) -> Revision {
+        self.db.salsa_runtime().current_revision()
+    }
+
+    /// True if no new revision has been requested since this snapshot
+    /// was taken. Once this returns false, results computed from the
+    /// snapshot are about to become stale (e.g., a completion list
+    /// for text that has since been edited) and should typically be
+    /// discarded rather than sent on.
+    ///
+    /// This is meant to be called outside of queries. It is based on
+    /// `is_current_revision_canceled`, so when a query calls it and it
+    /// returns false, the query is marked as having an untracked
+    /// input and will be re-executed in the next revision.
+    pub fn still_current(&self) -> bool {
+        !self.db.salsa_runtime().is_current_revision_canceled()
+    }
 }
 
 impl<DB> std::ops::Deref for Snapshot<DB>
@@ -409,6 +434,11 @@ pub trait Query<DB: Database>: Debug + Default + Sized + 'static {
     /// What value does the query return?
     type Value: Clone + Debug;
 
+    /// The doc comments written on the query's declaration in its
+    /// query group (empty if there are none), for display in
+    /// debugging and admin tools.
+    const DESCRIPTION: &'static str = "";
+
     /// Internal struct storing the values for the query.
     type Storage: plumbing::QueryStorageOps<DB, Self>;
 
@@ -430,6 +460,15 @@ pub trait Query<DB: Database>: Debug + Default + Sized + 'static {
 
     /// Create group key for this query.
     fn group_key(key: Self::Key) -> Self::GroupKey;
+
+    /// Maps a key to its canonical form before it is used to look up
+    /// the memoized value, so that logically equivalent keys (e.g.,
+    /// paths that differ only in normalization) share a single memo.
+    /// Defaults to the identity; derived queries can override it with
+    /// `#[salsa::canonicalize(path::to::fn)]`.
+    fn canonicalize_key(key: Self::Key) -> Self::Key {
+        key
+    }
 }
 
 /// Return value from [the `query` method] on `Database`.
@@ -456,16 +495,157 @@ where
     /// queries (those with no inputs, or those with more than one
     /// input) the key will be a tuple.
     pub fn get(&self, key: Q::Key) -> Q::Value {
+        let key = Q::canonicalize_key(key);
         let database_key = self.database_key(&key);
         self.storage
             .try_fetch(self.db, &key, &database_key)
-            .unwrap_or_else(|CycleDetected| {
-                self.db
+            .unwrap_or_else(|err| match err {
+                FetchError::Cycle => self
+                    .db
                     .salsa_runtime()
-                    .report_unexpected_cycle(database_key)
+                    .report_unexpected_cycle(database_key),
+                FetchError::UnsetInput => {
+                    panic!("no value set for {:?}({:?})", Q::default(), key)
+                }
             })
     }
 
+    /// Like `get`, but returns an error instead of panicking if the
+    /// value cannot be computed: because of a cycle, because it is an
+    /// input that was never set, because the current revision (or
+    /// this runtime's `cancellation_token`) was canceled, or because
+    /// the query or one of its dependencies panicked, on this thread
+    /// or on another one (see `Database::on_propagated_panic`).
+    ///
+    /// When invoked from within a query, an error other than a cycle
+    /// or an unset input causes that query to be re-executed in the
+    /// next revision.
+    pub fn try_get(&self, key: Q::Key) -> Result<Q::Value, QueryError<DB>> {
+        let key = Q::canonicalize_key(key);
+        let database_key = self.database_key(&key);
+        let runtime = self.db.salsa_runtime();
+        if runtime.is_canceled() {
+            return Err(QueryError::Canceled);
+        }
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            self.storage.try_fetch(self.db, &key, &database_key)
+        }));
+        match result {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(FetchError::Cycle)) => Err(QueryError::Cycle {
+                cycle: runtime.find_cycle(&database_key),
+            }),
+            Ok(Err(FetchError::UnsetInput)) => Err(QueryError::UnsetInput { database_key }),
+            Err(payload) => {
+                // The panic prevented the read from being recorded.
+                runtime.report_untracked_read();
+                Err(QueryError::Panicked {
+                    database_key,
+                    payload,
+                })
+            }
+        }
+    }
+
+    /// Interns each of `keys`, returning the interned values in the
+    /// same order. This is equivalent to invoking `get` for each key,
+    /// but acquires the interner's write lock only once for the whole
+    /// batch, which is much faster when interning many keys at once
+    /// (e.g., when importing metadata for a crate). Duplicate keys are
+    /// interned once; new keys are assigned ids in the order of their
+    /// first occurrence.
+    pub fn intern_many(&self, keys: impl IntoIterator<Item = Q::Key>) -> Vec<Q::Value>
+    where
+        Q::Storage: plumbing::InternedQueryStorageOps<DB, Q>,
+    {
+        let keys = keys.into_iter().map(|key| {
+            let database_key = self.database_key(&key);
+            (key, database_key)
+        });
+        self.storage.intern_many(self.db, keys)
+    }
+
+    /// Returns statistics about the tables of this interned query,
+    /// such as the number of interned keys and the lookup hit rate.
+    pub fn intern_stats(&self) -> debug::InternStats
+    where
+        Q::Storage: plumbing::InternedQueryStorageOps<DB, Q>,
+    {
+        self.storage.stats(self.db)
+    }
+
+    /// Invokes `op` with a reference to the data of the interned id
+    /// `key`, without cloning it. This is the by-reference form of
+    /// `get` for the `lookup_` queries of `#[salsa::interned]`
+    /// queries. The intern tables stay locked for reading while `op`
+    /// runs, so the data cannot be garbage collected meanwhile, but
+    /// `op` must not intern new values into the same query, directly
+    /// or through other queries: that would deadlock.
+    pub fn with_value<R>(&self, key: Q::Key, op: impl FnOnce(&Q::Value) -> R) -> R
+    where
+        Q::Storage: plumbing::LookupQueryStorageOps<DB, Q>,
+    {
+        let database_key = self.database_key(&key);
+        self.storage.with_value(self.db, &key, &database_key, op)
+    }
+
+    /// Returns the memoized value of this derived query for `key`
+    /// without executing the query or blocking on other threads, or
+    /// `None` if no value is memoized. The value may be stale, i.e.
+    /// computed in an earlier revision, which makes this useful to
+    /// show "best available" data instantly. When called from within a
+    /// query, that query is re-executed in each new revision.
+    pub fn peek(&self, key: Q::Key) -> Option<Q::Value>
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        let key = Q::canonicalize_key(key);
+        self.storage.fetch_if_cached(self.db, &key)
+    }
+
+    /// Fetches the value of this derived query for `key` (executing
+    /// it if needed, like `get`) and returns the metadata that its
+    /// most recent execution attached with `Runtime::attach_meta`.
+    /// Returns `None` if no metadata of type `M` was attached.
+    pub fn fetch_meta<M>(&self, key: Q::Key) -> Option<std::sync::Arc<M>>
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+        M: std::any::Any + Send + Sync,
+    {
+        let key = Q::canonicalize_key(key);
+        self.get(key.clone());
+        self.storage.meta(self.db, &key)?.downcast().ok()
+    }
+
+    /// Returns statistics about the executions of this derived query,
+    /// such as the peak number of threads that executed it at once.
+    pub fn execution_stats(&self) -> debug::ExecutionStats
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        self.storage.execution_stats(self.db)
+    }
+
+    /// Re-executes this derived query for every key whose memoized
+    /// value is up to date and panics if any result differs from the
+    /// memo (as judged by the query's memoization policy). Intended
+    /// for tests and CI, to catch query functions that are not
+    /// deterministic or that read state not tracked by salsa.
+    /// Volatile and mocked values are skipped.
+    pub fn verify_all(&self)
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        let keys = self
+            .storage
+            .map_entries(self.db, |key, value| value.map(|_| key.clone()));
+        for key in keys.into_iter().flatten() {
+            let database_key = self.database_key(&key);
+            self.storage.verify(self.db, &key, &database_key);
+        }
+    }
+
     /// Remove all values for this query that have not been used in
     /// the most recent revision.
     pub fn sweep(&self, strategy: SweepStrategy)
@@ -476,10 +656,102 @@ where
     }
 
     fn database_key(&self, key: &Q::Key) -> DB::DatabaseKey {
-        <DB as plumbing::GetQueryTable<Q>>::database_key(&self.db, key.clone())
+        <DB as plumbing::GetQueryTable<Q>>::database_key(self.db, key.clone())
     }
 }
 
+/// The ways in which [`QueryTable::try_get`] can fail.
+///
+/// [`QueryTable::try_get`]: struct.QueryTable.html#method.try_get
+#[non_exhaustive]
+pub enum QueryError<DB: Database> {
+    /// Computing the value would require the value itself. `cycle`
+    /// lists the queries that form the cycle, outermost first.
+    Cycle {
+        /// The queries on the cycle.
+        cycle: Vec<DB::DatabaseKey>,
+    },
+
+    /// The query is an input whose value was never set.
+    UnsetInput {
+        /// The input that was read.
+        database_key: DB::DatabaseKey,
+    },
+
+    /// The current revision was canceled (see
+    /// `Runtime::is_canceled`), so the value was not computed.
+    Canceled,
+
+    /// Computing the value panicked, either in the query itself or in
+    /// one of its dependencies.
+    Panicked {
+        /// The query that was requested.
+        database_key: DB::DatabaseKey,
+        /// The panic payload, which can be passed to
+        /// `std::panic::resume_unwind` to continue unwinding.
+        payload: Box<dyn std::any::Any + Send>,
+    },
+}
+
+impl<DB: Database> QueryError<DB> {
+    /// Returns the message of a `Panicked` error, if its payload is a
+    /// string (as produced by `panic!`).
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            QueryError::Panicked { payload, .. } => payload
+                .downcast_ref::<&'static str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            _ => None,
+        }
+    }
+}
+
+impl<DB: Database> Debug for QueryError<DB> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Cycle { cycle } => fmt.debug_struct("Cycle").field("cycle", cycle).finish(),
+            QueryError::UnsetInput { database_key } => fmt
+                .debug_struct("UnsetInput")
+                .field("database_key", database_key)
+                .finish(),
+            QueryError::Canceled => fmt.write_str("Canceled"),
+            QueryError::Panicked { database_key, .. } => fmt
+                .debug_struct("Panicked")
+                .field("database_key", database_key)
+                .field("message", &self.panic_message())
+                .finish(),
+        }
+    }
+}
+
+impl<DB: Database> fmt::Display for QueryError<DB> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Cycle { cycle } => {
+                write!(fmt, "cycle detected:")?;
+                for database_key in cycle {
+                    write!(fmt, "\n- {:?}", database_key)?;
+                }
+                Ok(())
+            }
+            QueryError::UnsetInput { database_key } => {
+                write!(fmt, "no value set for {:?}", database_key)
+            }
+            QueryError::Canceled => write!(fmt, "revision canceled"),
+            QueryError::Panicked { database_key, .. } => {
+                write!(fmt, "{:?} panicked", database_key)?;
+                if let Some(message) = self.panic_message() {
+                    write!(fmt, ": {}", message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<DB: Database> std::error::Error for QueryError<DB> {}
+
 /// Return value from [the `query_mut` method] on `Database`.
 /// Gives access to the `set` method, notably, that is used to
 /// set the value of an input query.
@@ -501,7 +773,7 @@ where
     Q: Query<DB>,
 {
     fn database_key(&self, key: &Q::Key) -> DB::DatabaseKey {
-        <DB as plumbing::GetQueryTable<Q>>::database_key(&self.db, key.clone())
+        <DB as plumbing::GetQueryTable<Q>>::database_key(self.db, key.clone())
     }
 
     /// Assign a value to an "input query". Must be used outside of
@@ -534,6 +806,89 @@ where
         self.storage
             .set_constant(self.db, &key, &self.database_key(&key), value);
     }
+
+    /// Assigns values to many keys of an "input query" at once. Unlike
+    /// calling `set` in a loop, this creates only a single new
+    /// revision (none if `values` is empty), which makes it cheaper
+    /// to load large numbers of inputs. Panics, without setting
+    /// anything, if one of the keys was set with `set_constant`. Must
+    /// be used outside of an active query computation.
+    ///
+    /// If you are using `snapshot`, see the notes on blocking
+    /// and cancellation on [the `query_mut` method].
+    ///
+    /// [the `query_mut` method]: trait.Database#method.query_mut
+    pub fn set_many(&self, values: impl IntoIterator<Item = (Q::Key, Q::Value)>)
+    where
+        Q::Storage: plumbing::InputQueryStorageOps<DB, Q>,
+    {
+        let values = values.into_iter().map(|(key, value)| {
+            let database_key = self.database_key(&key);
+            (key, database_key, value)
+        });
+        self.storage.set_many(self.db, values)
+    }
+
+    /// Removes the values of an "input query" for all keys for which
+    /// `keep` returns false, e.g. when a project is closed, and
+    /// compacts the storage. This creates a single new revision in
+    /// which the removed inputs count as changed (or none, if nothing
+    /// is removed), so derived values that read them will no longer
+    /// validate. Their memos are not removed eagerly, since salsa does
+    /// not track which memos read a given input; use `sweep` to
+    /// discard them. Panics, without removing anything, if an input
+    /// that was set with `set_constant` would be removed. Must be used
+    /// outside of an active query computation.
+    ///
+    /// If you are using `snapshot`, see the notes on blocking
+    /// and cancellation on [the `query_mut` method].
+    ///
+    /// [the `query_mut` method]: trait.Database#method.query_mut
+    pub fn retain(&self, keep: impl FnMut(&Q::Key) -> bool)
+    where
+        Q::Storage: plumbing::InputQueryStorageOps<DB, Q>,
+    {
+        self.storage.retain(self.db, keep)
+    }
+
+    /// Registers a closure to use as the implementation of a derived
+    /// query in place of its query function, e.g. to stub out an
+    /// expensive query in tests or to let a plugin supply it. Must be
+    /// called before the query is first executed (typically right
+    /// after creating the database); panics otherwise.
+    pub fn register_impl(
+        &self,
+        implementation: impl Fn(&DB, Q::Key) -> Q::Value + Send + Sync + 'static,
+    ) where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        self.storage.register_impl(self.db, implementation);
+    }
+
+    /// Overrides the result of a derived query for `key`: until
+    /// `unmock` is called, fetching `key` returns `value` without
+    /// executing the query. This is meant for tests that want to
+    /// isolate downstream queries from expensive or nondeterministic
+    /// upstream ones. Like `set`, this creates a new revision.
+    pub fn mock(&self, key: Q::Key, value: Q::Value)
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        let key = Q::canonicalize_key(key);
+        self.storage
+            .mock(self.db, &key, &self.database_key(&key), value);
+    }
+
+    /// Removes an override installed by `mock`, so that `key` is
+    /// computed by the query again. Does nothing if `key` is not
+    /// mocked.
+    pub fn unmock(&self, key: Q::Key)
+    where
+        Q::Storage: plumbing::DerivedQueryStorageOps<DB, Q>,
+    {
+        let key = Q::canonicalize_key(key);
+        self.storage.unmock(self.db, &key, &self.database_key(&key));
+    }
 }
 
 // Re-export the procedural macros.
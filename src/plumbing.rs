@@ -1,13 +1,17 @@
 #![allow(missing_docs)]
 
+use crate::debug::ExecutionStats;
+use crate::debug::InternStats;
 use crate::debug::TableEntry;
 use crate::Database;
 use crate::Query;
 use crate::QueryTable;
 use crate::QueryTableMut;
 use crate::SweepStrategy;
+use std::any::Any;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::sync::Arc;
 
 pub use crate::derived::DependencyStorage;
 pub use crate::derived::MemoizedStorage;
@@ -19,6 +23,22 @@ pub use crate::runtime::Revision;
 
 pub struct CycleDetected;
 
+/// The reasons that `QueryStorageOps::try_fetch` can fail.
+#[derive(Debug)]
+pub enum FetchError {
+    /// Computing the value would require the value itself.
+    Cycle,
+
+    /// The query is an input whose value has not been set.
+    UnsetInput,
+}
+
+impl From<CycleDetected> for FetchError {
+    fn from(CycleDetected: CycleDetected) -> Self {
+        FetchError::Cycle
+    }
+}
+
 /// Defines various associated types. An impl of this
 /// should be generated for your query-context type automatically by
 /// the `database_storage` macro, so you shouldn't need to mess
@@ -59,6 +79,20 @@ pub trait DatabaseKey<DB>: Clone + Debug + Eq + Hash {
 
 pub trait QueryFunction<DB: Database>: Query<DB> {
     fn execute(db: &DB, key: Self::Key) -> Self::Value;
+
+    /// Checks invariants of a newly computed value; invoked after
+    /// each execution in debug builds. Its reads are not recorded as
+    /// dependencies of the query. The default does nothing.
+    #[allow(unused_variables)]
+    fn validate(db: &DB, key: &Self::Key, value: &Self::Value) {}
+
+    /// Produces a fallback value when fetching `key` would complete
+    /// the given `cycle` (outermost query first). The default returns
+    /// `None`, in which case the cycle is reported as an error.
+    #[allow(unused_variables)]
+    fn recover(db: &DB, cycle: &[DB::DatabaseKey], key: &Self::Key) -> Option<Self::Value> {
+        None
+    }
 }
 
 /// The `GetQueryTable` trait makes the connection the *database type*
@@ -142,13 +176,13 @@ where
     ///
     /// Returns `Err` in the event of a cycle, meaning that computing
     /// the value for this `key` is recursively attempting to fetch
-    /// itself.
+    /// itself, or if `key` is an input that has not been set.
     fn try_fetch(
         &self,
         db: &DB,
         key: &Q::Key,
         descriptor: &DB::DatabaseKey,
-    ) -> Result<Q::Value, CycleDetected>;
+    ) -> Result<Q::Value, FetchError>;
 
     /// True if the query **may** have changed since the given
     /// revision. The query will answer this question with as much
@@ -183,6 +217,10 @@ where
     fn entries<C>(&self, db: &DB) -> C
     where
         C: std::iter::FromIterator<TableEntry<Q::Key, Q::Value>>;
+
+    /// Invoke `op` on each entry in the query storage, passing the key
+    /// and (if stored) the value by reference, and collect the results.
+    fn map_entries<R>(&self, db: &DB, op: impl FnMut(&Q::Key, Option<&Q::Value>) -> R) -> Vec<R>;
 }
 
 /// An optional trait that is implemented for "user mutable" storage:
@@ -202,4 +240,95 @@ where
         descriptor: &DB::DatabaseKey,
         new_value: Q::Value,
     );
+
+    /// Sets all of the given inputs in a single new revision.
+    fn set_many(
+        &self,
+        db: &DB,
+        values: impl IntoIterator<Item = (Q::Key, DB::DatabaseKey, Q::Value)>,
+    );
+
+    /// Removes the inputs whose keys do not satisfy `keep`, in a
+    /// single new revision.
+    fn retain(&self, db: &DB, keep: impl FnMut(&Q::Key) -> bool);
+}
+
+/// An optional trait that is implemented for derived storage: that
+/// is, storage whose value is computed by executing a query function.
+pub trait DerivedQueryStorageOps<DB, Q>: Default
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    /// Replaces the query function with `implementation` for all
+    /// future executions. Panics if the query has already been
+    /// executed.
+    fn register_impl(
+        &self,
+        db: &DB,
+        implementation: impl Fn(&DB, Q::Key) -> Q::Value + Send + Sync + 'static,
+    );
+
+    /// Makes `key` produce `value` without executing the query,
+    /// until `unmock` is called.
+    fn mock(&self, db: &DB, key: &Q::Key, descriptor: &DB::DatabaseKey, value: Q::Value);
+
+    /// Removes a value installed by `mock`.
+    fn unmock(&self, db: &DB, key: &Q::Key, descriptor: &DB::DatabaseKey);
+
+    /// Returns the memoized value for `key`, if there is one, without
+    /// executing the query or waiting on other threads. The value may
+    /// be stale.
+    fn fetch_if_cached(&self, db: &DB, key: &Q::Key) -> Option<Q::Value>;
+
+    /// Returns the metadata attached to the memo for `key`, if any.
+    fn meta(&self, db: &DB, key: &Q::Key) -> Option<Arc<dyn Any + Send + Sync>>;
+
+    /// Gathers statistics about the executions of the query.
+    fn execution_stats(&self, db: &DB) -> ExecutionStats;
+
+    /// If `key` has an up-to-date memoized value, executes the query
+    /// again and panics if the result differs from the memo.
+    fn verify(&self, db: &DB, key: &Q::Key, descriptor: &DB::DatabaseKey);
+}
+
+/// An optional trait that is implemented for interned storage: that
+/// is, storage that maps keys to freshly allocated intern-ids.
+pub trait InternedQueryStorageOps<DB, Q>: Default
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    /// Interns each of the given keys, returning the interned values
+    /// in the same order. Each key is paired with its descriptor so
+    /// that the reads can be reported to the active query.
+    fn intern_many(
+        &self,
+        db: &DB,
+        keys: impl IntoIterator<Item = (Q::Key, DB::DatabaseKey)>,
+    ) -> Vec<Q::Value>;
+
+    /// Gathers statistics about the intern tables.
+    fn stats(&self, db: &DB) -> InternStats;
+}
+
+/// An optional trait that is implemented for the storage of
+/// "interned lookup" queries, which map an interned id back to its
+/// data.
+pub trait LookupQueryStorageOps<DB, Q>: Default
+where
+    DB: Database,
+    Q: Query<DB>,
+{
+    /// Invokes `op` with a reference to the data for `key`, reporting
+    /// the read like a fetch would. The intern tables are locked for
+    /// reading while `op` runs, so `op` must not intern new values
+    /// into the same query.
+    fn with_value<R>(
+        &self,
+        db: &DB,
+        key: &Q::Key,
+        database_key: &DB::DatabaseKey,
+        op: impl FnOnce(&Q::Value) -> R,
+    ) -> R;
 }
@@ -0,0 +1,10 @@
+//! Re-exports the traits and types that most users of salsa need, so
+//! that a single `use salsa::prelude::*;` brings the database methods
+//! (`query`, `query_mut`, `snapshot`, ...) into scope.
+
+pub use crate::Database;
+pub use crate::InternId;
+pub use crate::InternKey;
+pub use crate::ParallelDatabase;
+pub use crate::Runtime;
+pub use crate::Snapshot;
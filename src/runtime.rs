@@ -6,14 +6,34 @@ use rustc_hash::{FxHashMap, FxHasher};
 use smallvec::SmallVec;
 use std::fmt::Write;
 use std::hash::BuildHasherDefault;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 pub(crate) type FxIndexSet<K> = indexmap::IndexSet<K, BuildHasherDefault<FxHasher>>;
+pub(crate) type FxIndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<FxHasher>>;
+
+/// User metadata attached to a memo via `Runtime::attach_meta`.
+pub(crate) type MemoMeta = Arc<dyn std::any::Any + Send + Sync>;
+
+mod cancellation;
+pub use cancellation::CancellationToken;
 
 mod local_state;
 use local_state::LocalState;
 
+mod recent_changes;
+use recent_changes::RecentChanges;
+
+mod revision_history;
+use revision_history::RevisionHistory;
+pub use revision_history::RevisionRecord;
+
+mod stalled_write;
+use stalled_write::StalledWriteHandler;
+pub use stalled_write::{SnapshotInfo, StalledWrite};
+
 /// The salsa runtime stores the storage for all queries as well as
 /// tracking the query stack and dependencies between cycles.
 ///
@@ -33,6 +53,9 @@ pub struct Runtime<DB: Database> {
     /// Local state that is specific to this runtime (thread).
     local_state: LocalState<DB>,
 
+    /// Cancels the work of this runtime only (see `is_canceled`).
+    cancellation_token: CancellationToken,
+
     /// Shared state that is accessible via all runtimes.
     shared_state: Arc<SharedState<DB>>,
 }
@@ -42,11 +65,16 @@ where
     DB: Database,
 {
     fn default() -> Self {
+        let shared_state: Arc<SharedState<DB>> = Default::default();
         Runtime {
-            id: RuntimeId { counter: 0 },
+            id: RuntimeId {
+                database: shared_state.database_id,
+                counter: 0,
+            },
             revision_guard: None,
-            shared_state: Default::default(),
+            shared_state,
             local_state: Default::default(),
+            cancellation_token: Default::default(),
         }
     }
 }
@@ -74,6 +102,24 @@ where
         Self::default()
     }
 
+    /// Create a new runtime in "one-shot" mode, intended for batch
+    /// tools that compute their results once and exit. In this mode,
+    /// queries do not record the dependencies they read and their
+    /// values are never backdated: results are still memoized within
+    /// a revision, but any new revision re-executes every derived
+    /// query that is requested. Snapshots inherit the mode.
+    pub fn new_one_shot() -> Self {
+        let mut runtime = Self::default();
+        Arc::get_mut(&mut runtime.shared_state).unwrap().one_shot = true;
+        runtime
+    }
+
+    /// True if this runtime was created with `new_one_shot`.
+    #[inline]
+    pub fn is_one_shot(&self) -> bool {
+        self.shared_state.one_shot
+    }
+
     /// Returns the underlying storage, where the keys/values for all queries are kept.
     pub fn storage(&self) -> &DB::DatabaseStorage {
         &self.shared_state.storage
@@ -98,17 +144,19 @@ where
             panic!("it is not legal to `snapshot` during a query (see salsa-rs/salsa#80)");
         }
 
-        let revision_guard = RevisionGuard::new(&self.shared_state);
-
         let id = RuntimeId {
+            database: self.shared_state.database_id,
             counter: self.shared_state.next_id.fetch_add(1, Ordering::SeqCst),
         };
 
+        let revision_guard = RevisionGuard::new(&self.shared_state, id);
+
         Runtime {
             id,
             revision_guard: Some(revision_guard),
             shared_state: self.shared_state.clone(),
             local_state: Default::default(),
+            cancellation_token: Default::default(),
         }
     }
 
@@ -123,7 +171,33 @@ where
     /// case, you can wrap the input with a "no-storage" query and
     /// invoke this method from time to time.
     pub fn next_revision(&self) {
-        self.with_incremented_revision(|_| ());
+        self.with_incremented_revision(None, false, |_| ());
+    }
+
+    /// Returns the most recent revisions, oldest first, along with
+    /// the input change that triggered each of them. Nothing is
+    /// recorded unless a capacity has been configured with
+    /// [`set_revision_history_capacity`].
+    ///
+    /// This is meant for performance dashboards and the like, which
+    /// may wish to correlate slow queries with the inputs that
+    /// changed.
+    ///
+    /// [`set_revision_history_capacity`]: struct.Runtime.html#method.set_revision_history_capacity
+    pub fn revision_history(&self) -> Vec<RevisionRecord<DB>> {
+        self.shared_state.revision_history.lock().records()
+    }
+
+    /// Sets the number of revisions retained by
+    /// [`revision_history`]. Older records are discarded first. A
+    /// capacity of zero (the default) disables the history.
+    ///
+    /// [`revision_history`]: struct.Runtime.html#method.revision_history
+    pub fn set_revision_history_capacity(&self, capacity: usize) {
+        self.shared_state
+            .revision_history
+            .lock()
+            .set_capacity(capacity);
     }
 
     /// Default implementation for `Database::sweep_all`.
@@ -136,6 +210,26 @@ where
         db.for_each_query(|query_storage| query_storage.sweep(db, strategy));
     }
 
+    /// Puts the database into read-only mode until the returned guard
+    /// is dropped. While any such guard exists, attempting to set an
+    /// input or to create a new revision **panics**, rather than
+    /// blocking or silently succeeding. This lets code paths that
+    /// are meant to be read-only (e.g., request handlers serving
+    /// from a snapshot) guarantee that they never mutate the database
+    /// by accident.
+    ///
+    /// The guard applies to the database as a whole, including all of
+    /// its snapshots, and guards may be nested.
+    pub fn freeze(&self) -> FreezeGuard<DB> {
+        FreezeGuard::new(&self.shared_state)
+    }
+
+    /// True if there is at least one live guard returned by
+    /// [`freeze`](struct.Runtime.html#method.freeze).
+    pub fn is_frozen(&self) -> bool {
+        self.shared_state.freeze_count.load(Ordering::SeqCst) > 0
+    }
+
     /// The unique identifier attached to this `SalsaRuntime`. Each
     /// snapshotted runtime has a distinct identifier.
     #[inline]
@@ -143,15 +237,25 @@ where
         self.id
     }
 
+    /// The identifier of the database that this runtime belongs to.
+    /// It is shared by all snapshots of the database, and is also
+    /// part of every `RuntimeId`, and thus of events and debug output.
+    #[inline]
+    pub fn database_id(&self) -> DatabaseId {
+        self.shared_state.database_id
+    }
+
     /// Returns the database-key for the query that this thread is
     /// actively executing (if any).
     pub fn active_query(&self) -> Option<DB::DatabaseKey> {
         self.local_state.active_query()
     }
 
-    /// Read current value of the revision counter.
+    /// Read current value of the revision counter. Results computed
+    /// in a given revision can be tagged with it and later compared
+    /// against the current revision to detect staleness.
     #[inline]
-    pub(crate) fn current_revision(&self) -> Revision {
+    pub fn current_revision(&self) -> Revision {
         Revision {
             generation: self.shared_state.revision.load(Ordering::SeqCst) as u64,
         }
@@ -165,6 +269,32 @@ where
         }
     }
 
+    /// Returns the token that cancels the work of this runtime only.
+    /// Each snapshot gets a fresh token, so a caller can hand it to
+    /// another thread and cancel one snapshot's work explicitly, or
+    /// give it a deadline, without writing to the database. Queries
+    /// observe it through `is_canceled`. A snapshot never leaves its
+    /// revision, but the token of the master runtime only applies to
+    /// the current revision: it is reset whenever a new revision
+    /// begins.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Like `is_current_revision_canceled`, but also returns true if
+    /// this runtime's `cancellation_token` was canceled or its
+    /// deadline has passed. Queries that poll for cancellation should
+    /// prefer this method.
+    pub fn is_canceled(&self) -> bool {
+        if self.cancellation_token.is_canceled() {
+            // The result of a query that observed this is only valid
+            // for this execution.
+            self.report_untracked_read();
+            return true;
+        }
+        self.is_current_revision_canceled()
+    }
+
     /// Check if the current revision is canceled. If this method ever
     /// returns true, the currently executing query is also marked as
     /// having an *untracked read* -- this means that, in the next
@@ -263,23 +393,36 @@ where
     /// Note that, given our writer model, we can assume that only one
     /// thread is attempting to increment the global revision at a
     /// time.
-    pub(crate) fn with_incremented_revision<R>(&self, op: impl FnOnce(Revision) -> R) -> R {
+    ///
+    /// `changed_input` and `constant` describe the input change that
+    /// triggered the new revision, if any, and are recorded in the
+    /// revision history.
+    pub(crate) fn with_incremented_revision<R>(
+        &self,
+        changed_input: Option<&DB::DatabaseKey>,
+        constant: bool,
+        op: impl FnOnce(Revision) -> R,
+    ) -> R {
         log::debug!("increment_revision()");
 
         if !self.permits_increment() {
             panic!("increment_revision invoked during a query computation");
         }
 
+        if self.is_frozen() {
+            panic!("attempted to modify the database while it is frozen (see `Runtime::freeze`)");
+        }
+
         // Set the `pending_revision` field so that people
         // know current revision is canceled.
         let current_revision = self
             .shared_state
             .pending_revision
             .fetch_add(1, Ordering::SeqCst);
-        assert!(current_revision != usize::max_value(), "revision overflow");
+        assert!(current_revision != usize::MAX, "revision overflow");
 
         // To modify the revision, we need the lock.
-        let _lock = self.shared_state.query_lock.write();
+        let _lock = self.lock_for_write();
 
         let old_revision = self.shared_state.revision.fetch_add(1, Ordering::SeqCst);
         assert_eq!(current_revision, old_revision);
@@ -290,9 +433,112 @@ where
 
         debug!("increment_revision: incremented to {:?}", new_revision);
 
+        // Cancellation of the previous revision's work does not carry
+        // over into the new one.
+        self.cancellation_token.reset();
+
+        self.shared_state
+            .revision_history
+            .lock()
+            .record(RevisionRecord {
+                revision: new_revision,
+                timestamp: SystemTime::now(),
+                changed_input: changed_input.cloned(),
+                constant,
+            });
+
         op(new_revision)
     }
 
+    /// Records that the value of `database_key` was computed in
+    /// `revision` for the first time, or recomputed and found to
+    /// differ from its previous value.
+    pub(crate) fn report_changed_value(&self, revision: Revision, database_key: &DB::DatabaseKey) {
+        if !self.shared_state.records_changes.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.shared_state
+            .recent_changes
+            .lock()
+            .record(revision, database_key);
+    }
+
+    /// Returns the derived values that were computed in `revision`
+    /// for the first time, or recomputed and found to differ from
+    /// their previous value (i.e., that were not backdated), in the
+    /// order they were computed. Values that are not memoized (see
+    /// `#[salsa::dependencies]`) cannot be compared and are only
+    /// listed when first computed. Values are
+    /// only recomputed when someone asks for them, so this list grows
+    /// as queries are fetched. Only the most recent revisions are
+    /// retained (see `set_recent_changes_capacity`); older revisions
+    /// yield an empty list.
+    pub fn changes_in(&self, revision: Revision) -> Vec<DB::DatabaseKey> {
+        self.shared_state.recent_changes.lock().changes_in(revision)
+    }
+
+    /// Configures how many revisions `changes_in` retains. The default
+    /// is zero, in which case nothing is recorded.
+    pub fn set_recent_changes_capacity(&self, capacity: usize) {
+        self.shared_state
+            .recent_changes
+            .lock()
+            .set_capacity(capacity);
+        self.shared_state
+            .records_changes
+            .store(capacity > 0, Ordering::Relaxed);
+    }
+
+    /// Acquires the query lock for writing, invoking the stalled-write
+    /// handler (if any) each time the configured timeout elapses.
+    fn lock_for_write(&self) -> parking_lot::RwLockWriteGuard<'_, ()> {
+        let handler = self.shared_state.stalled_write_handler.lock().clone();
+        let (timeout, handler) = match handler {
+            Some(handler) => handler,
+            None => return self.shared_state.query_lock.write(),
+        };
+
+        let start = Instant::now();
+        loop {
+            if let Some(lock) = self.shared_state.query_lock.try_write_for(timeout) {
+                return lock;
+            }
+
+            let snapshots = self
+                .shared_state
+                .snapshots
+                .lock()
+                .values()
+                .cloned()
+                .collect();
+            handler(&StalledWrite {
+                waited: start.elapsed(),
+                snapshots,
+            });
+        }
+    }
+
+    /// Installs a handler that is invoked when a write (e.g., a call
+    /// to `set`) has been blocked for `timeout` waiting for snapshots
+    /// to be dropped, and again after each further `timeout` that it
+    /// keeps waiting. The handler receives the live snapshots, so it
+    /// can identify the holder that is not checking for cancellation.
+    /// Only snapshots created after the handler is installed are
+    /// reported; until then, snapshots are not tracked at all.
+    ///
+    /// The handler runs on the writing thread, while it waits.
+    pub fn set_stalled_write_handler(
+        &self,
+        timeout: Duration,
+        handler: impl Fn(&StalledWrite) + Send + Sync + 'static,
+    ) {
+        *self.shared_state.stalled_write_handler.lock() = Some((timeout, Arc::new(handler)));
+        self.shared_state
+            .tracks_snapshots
+            .store(true, Ordering::SeqCst);
+    }
+
     pub(crate) fn permits_increment(&self) -> bool {
         self.revision_guard.is_none() && !self.local_state.query_in_progress()
     }
@@ -312,26 +558,89 @@ where
             },
         });
 
-        // Push the active query onto the stack.
-        let active_query = self.local_state.push_query(database_key);
-
         // Execute user's code, accumulating inputs etc.
-        let value = execute();
-
-        // Extract accumulated inputs.
-        let ActiveQuery {
-            subqueries,
-            changed_at,
-            ..
-        } = active_query.complete();
+        let (
+            value,
+            ActiveQuery {
+                subqueries,
+                changed_at,
+                meta,
+                ..
+            },
+        ) = self.with_query_pushed(database_key, !self.shared_state.one_shot, execute);
 
         ComputedQueryResult {
             value,
             changed_at,
             subqueries,
+            meta,
         }
     }
 
+    /// Runs `op` on behalf of `database_key` without recording its
+    /// reads as dependencies of the active query (if any). Used for
+    /// checks that inspect a computed value, such as
+    /// `QueryFunction::validate`.
+    pub(crate) fn execute_untracked<V>(
+        &self,
+        database_key: &DB::DatabaseKey,
+        op: impl FnOnce() -> V,
+    ) -> V {
+        self.with_query_pushed(database_key, false, op).0
+    }
+
+    /// Pushes `database_key` onto the query stack while `op` runs,
+    /// returning the value along with the accumulated inputs. If `op`
+    /// panics, the query stack is reported before the panic is
+    /// propagated, unchanged.
+    fn with_query_pushed<V>(
+        &self,
+        database_key: &DB::DatabaseKey,
+        track_inputs: bool,
+        op: impl FnOnce() -> V,
+    ) -> (V, ActiveQuery<DB>) {
+        let active_query = self.local_state.push_query(database_key, track_inputs);
+
+        let value = match panic::catch_unwind(AssertUnwindSafe(op)) {
+            Ok(value) => value,
+            Err(payload) => {
+                self.report_query_stack();
+                panic::resume_unwind(payload)
+            }
+        };
+
+        (value, active_query.complete())
+    }
+
+    /// Prints the active query stack to stderr, innermost query first,
+    /// right after the panic hook has printed the panic message. Only
+    /// the innermost query that observes a panic reports it; the
+    /// queries it propagates through stay quiet.
+    fn report_query_stack(&self) {
+        if !self.local_state.report_panic() {
+            return;
+        }
+
+        let mut message = format!("salsa query stack ({:?}):", self.id());
+        let query_stack = self.local_state.borrow_query_stack();
+        for (index, active_query) in query_stack.iter().rev().enumerate() {
+            write!(message, "\n  {}: {:?}", index, active_query.database_key).unwrap();
+        }
+        eprintln!("{}", message);
+    }
+
+    /// Attaches `meta` (e.g., a content hash of the result, or how long
+    /// it took to compute) to the memo of the currently executing
+    /// derived query, replacing any metadata attached earlier in the
+    /// same execution. The metadata lives and dies with the memo and
+    /// can be read with `QueryTable::fetch_meta`. It is not tracked as
+    /// part of the value: backdating compares values only.
+    ///
+    /// Panics if no query is executing.
+    pub fn attach_meta(&self, meta: impl std::any::Any + Send + Sync) {
+        self.local_state.attach_meta(Arc::new(meta));
+    }
+
     /// Reports that the currently active query read the result from
     /// another query.
     ///
@@ -365,18 +674,31 @@ where
     pub(crate) fn report_unexpected_cycle(&self, database_key: DB::DatabaseKey) -> ! {
         debug!("report_unexpected_cycle(database_key={:?})", database_key);
 
+        let mut message = "Internal error, cycle detected:\n".to_string();
+        for database_key in self.find_cycle(&database_key) {
+            writeln!(message, "- {:?}\n", database_key).unwrap();
+        }
+        panic!("{}", message)
+    }
+
+    /// Returns the queries on the active query stack that form a
+    /// cycle with `database_key`, outermost first.
+    pub(crate) fn find_cycle(&self, database_key: &DB::DatabaseKey) -> Vec<DB::DatabaseKey> {
         let query_stack = self.local_state.borrow_query_stack();
         let start_index = (0..query_stack.len())
             .rev()
-            .filter(|&i| query_stack[i].database_key == database_key)
-            .next()
-            .unwrap();
+            .find(|&i| query_stack[i].database_key == *database_key);
 
-        let mut message = format!("Internal error, cycle detected:\n");
-        for active_query in &query_stack[start_index..] {
-            writeln!(message, "- {:?}\n", active_query.database_key).unwrap();
+        match start_index {
+            Some(start_index) => query_stack[start_index..]
+                .iter()
+                .map(|active_query| active_query.database_key.clone())
+                .collect(),
+
+            // The cycle runs through another thread, whose stack we
+            // cannot see.
+            None => vec![database_key.clone()],
         }
-        panic!(message)
     }
 
     /// Try to make this runtime blocked on `other_id`. Returns true
@@ -400,6 +722,9 @@ where
 struct SharedState<DB: Database> {
     storage: DB::DatabaseStorage,
 
+    /// Identifies this database among all databases in the process.
+    database_id: DatabaseId,
+
     /// Stores the next id to use for a snapshotted runtime (starts at 1).
     next_id: AtomicUsize,
 
@@ -429,6 +754,36 @@ struct SharedState<DB: Database> {
     /// The dependency graph tracks which runtimes are blocked on one
     /// another, waiting for queries to terminate.
     dependency_graph: Mutex<DependencyGraph<DB>>,
+
+    /// Log of the most recent revisions (see `Runtime::revision_history`).
+    revision_history: Mutex<RevisionHistory<DB>>,
+
+    /// Changed values of the most recent revisions (see
+    /// `Runtime::changes_in`).
+    recent_changes: Mutex<RecentChanges<DB>>,
+
+    /// True if `recent_changes` has a non-zero capacity, so that
+    /// changes need not be reported to it otherwise.
+    records_changes: AtomicBool,
+
+    /// Number of live `FreezeGuard`s; while non-zero, no new revision
+    /// can be created.
+    freeze_count: AtomicUsize,
+
+    /// True if dependency tracking is disabled (see
+    /// `Runtime::new_one_shot`).
+    one_shot: bool,
+
+    /// The live snapshots, by the id of their runtime. Only recorded
+    /// once a stalled-write handler is installed.
+    snapshots: Mutex<FxHashMap<RuntimeId, SnapshotInfo>>,
+
+    /// True if a stalled-write handler is installed, in which case
+    /// snapshots are recorded in `snapshots`.
+    tracks_snapshots: AtomicBool,
+
+    /// See `Runtime::set_stalled_write_handler`.
+    stalled_write_handler: Mutex<Option<(Duration, StalledWriteHandler)>>,
 }
 
 impl<DB> std::panic::RefUnwindSafe for SharedState<DB>
@@ -441,12 +796,21 @@ where
 impl<DB: Database> Default for SharedState<DB> {
     fn default() -> Self {
         SharedState {
+            database_id: DatabaseId::next(),
             next_id: AtomicUsize::new(1),
             storage: Default::default(),
             query_lock: Default::default(),
             revision: Default::default(),
             pending_revision: Default::default(),
             dependency_graph: Default::default(),
+            revision_history: Default::default(),
+            recent_changes: Default::default(),
+            records_changes: Default::default(),
+            freeze_count: Default::default(),
+            one_shot: false,
+            snapshots: Default::default(),
+            tracks_snapshots: Default::default(),
+            stalled_write_handler: Default::default(),
         }
     }
 }
@@ -464,6 +828,7 @@ where
             "<wlocked>"
         };
         fmt.debug_struct("SharedState")
+            .field("database_id", &self.database_id)
             .field("query_lock", &query_lock)
             .field("revision", &self.revision)
             .field("pending_revision", &self.pending_revision)
@@ -484,6 +849,9 @@ struct ActiveQuery<DB: Database> {
     /// Set of subqueries that were accessed thus far, or `None` if
     /// there was an untracked the read.
     subqueries: Option<FxIndexSet<DB::DatabaseKey>>,
+
+    /// Metadata attached via `Runtime::attach_meta`.
+    meta: Option<MemoMeta>,
 }
 
 pub(crate) struct ComputedQueryResult<DB: Database, V> {
@@ -500,17 +868,25 @@ pub(crate) struct ComputedQueryResult<DB: Database, V> {
     /// Complete set of subqueries that were accessed, or `None` if
     /// there was an untracked the read.
     pub(crate) subqueries: Option<FxIndexSet<DB::DatabaseKey>>,
+
+    /// Metadata attached via `Runtime::attach_meta`, if any.
+    pub(crate) meta: Option<MemoMeta>,
 }
 
 impl<DB: Database> ActiveQuery<DB> {
-    fn new(database_key: DB::DatabaseKey) -> Self {
+    fn new(database_key: DB::DatabaseKey, track_inputs: bool) -> Self {
         ActiveQuery {
             database_key,
             changed_at: ChangedAt {
                 is_constant: true,
                 revision: Revision::ZERO,
             },
-            subqueries: Some(FxIndexSet::default()),
+            subqueries: if track_inputs {
+                Some(FxIndexSet::default())
+            } else {
+                None
+            },
+            meta: None,
         }
     }
 
@@ -544,14 +920,29 @@ impl<DB: Database> ActiveQuery<DB> {
 /// complete, its `RuntimeId` may potentially be re-used.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RuntimeId {
+    database: DatabaseId,
     counter: usize,
 }
 
+/// A unique identifier for a database within the process, so that
+/// events and logs from several databases can be told apart. All
+/// snapshots of a database share its `DatabaseId`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DatabaseId(u32);
+
+impl DatabaseId {
+    fn next() -> DatabaseId {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+        DatabaseId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 /// A unique identifier for the current version of the database; each
 /// time an input is changed, the revision number is incremented.
 /// `Revision` is used internally to track which values may need to be
-/// recomputed, but not something you should have to interact with
-/// directly as a user of salsa.
+/// recomputed. Users can obtain it (see `Runtime::current_revision`
+/// and `Snapshot::revision`) to tag results with the revision they
+/// were computed in, and compare revisions to detect stale results.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Revision {
     generation: u64,
@@ -567,7 +958,7 @@ impl Revision {
     }
 
     fn as_usize(self) -> usize {
-        assert!(self.generation < (std::usize::MAX as u64));
+        assert!(self.generation < (usize::MAX as u64));
         self.generation as usize
     }
 }
@@ -648,16 +1039,13 @@ impl<DB: Database> DependencyGraph<DB> {
         self.edges.insert(from_id, to_id);
         self.labels
             .entry(database_key.clone())
-            .or_insert(SmallVec::default())
+            .or_default()
             .push(from_id);
         true
     }
 
     fn remove_edge(&mut self, database_key: &DB::DatabaseKey, to_id: RuntimeId) {
-        let vec = self
-            .labels
-            .remove(database_key)
-            .unwrap_or(SmallVec::default());
+        let vec = self.labels.remove(database_key).unwrap_or_default();
 
         for from_id in &vec {
             let to_id1 = self.edges.remove(from_id);
@@ -668,13 +1056,14 @@ impl<DB: Database> DependencyGraph<DB> {
 
 struct RevisionGuard<DB: Database> {
     shared_state: Arc<SharedState<DB>>,
+    runtime_id: RuntimeId,
 }
 
 impl<DB> RevisionGuard<DB>
 where
     DB: Database,
 {
-    fn new(shared_state: &Arc<SharedState<DB>>) -> Self {
+    fn new(shared_state: &Arc<SharedState<DB>>, runtime_id: RuntimeId) -> Self {
         // Subtle: we use a "recursive" lock here so that it is not an
         // error to acquire a read-lock when one is already held (this
         // happens when a query uses `snapshot` to spawn off parallel
@@ -695,8 +1084,16 @@ where
             shared_state.query_lock.raw().lock_shared_recursive();
         }
 
+        if shared_state.tracks_snapshots.load(Ordering::SeqCst) {
+            shared_state
+                .snapshots
+                .lock()
+                .insert(runtime_id, SnapshotInfo::new(runtime_id));
+        }
+
         Self {
             shared_state: shared_state.clone(),
+            runtime_id,
         }
     }
 }
@@ -706,6 +1103,10 @@ where
     DB: Database,
 {
     fn drop(&mut self) {
+        if self.shared_state.tracks_snapshots.load(Ordering::SeqCst) {
+            self.shared_state.snapshots.lock().remove(&self.runtime_id);
+        }
+
         // Release our read-lock without using RAII. As documented in
         // `Snapshot::new` above, this requires the unsafe keyword.
         unsafe {
@@ -713,3 +1114,43 @@ where
         }
     }
 }
+
+/// Guard returned by [`Runtime::freeze`]; the database stays read-only
+/// until every such guard has been dropped.
+///
+/// [`Runtime::freeze`]: struct.Runtime.html#method.freeze
+pub struct FreezeGuard<DB: Database> {
+    shared_state: Arc<SharedState<DB>>,
+}
+
+impl<DB> FreezeGuard<DB>
+where
+    DB: Database,
+{
+    fn new(shared_state: &Arc<SharedState<DB>>) -> Self {
+        shared_state.freeze_count.fetch_add(1, Ordering::SeqCst);
+        Self {
+            shared_state: shared_state.clone(),
+        }
+    }
+}
+
+impl<DB> Drop for FreezeGuard<DB>
+where
+    DB: Database,
+{
+    fn drop(&mut self) {
+        self.shared_state
+            .freeze_count
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<DB> std::fmt::Debug for FreezeGuard<DB>
+where
+    DB: Database,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("FreezeGuard").finish()
+    }
+}
@@ -0,0 +1,71 @@
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A handle for cancelling the work of one particular runtime (that
+/// is, one snapshot), independently of pending writes; see
+/// [`Runtime::cancellation_token`](struct.Runtime.html#method.cancellation_token).
+/// Tokens are cheap to clone and can be sent to other threads.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+#[derive(Debug)]
+struct CancellationState {
+    canceled: AtomicBool,
+
+    /// The deadline, as nanoseconds since `created_at` (`u64::MAX`
+    /// if there is none). Stored as an integer so that the token
+    /// stays lock-free and unwind-safe.
+    deadline: AtomicU64,
+
+    created_at: Instant,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken {
+            state: Arc::new(CancellationState {
+                canceled: AtomicBool::new(false),
+                deadline: AtomicU64::new(u64::MAX),
+                created_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl CancellationToken {
+    /// Requests cancellation of the runtime's work.
+    pub fn cancel(&self) {
+        self.state.canceled.store(true, Ordering::SeqCst);
+    }
+
+    /// Requests cancellation of the runtime's work once `deadline` has
+    /// passed. Replaces any earlier deadline.
+    pub fn cancel_at(&self, deadline: Instant) {
+        let nanos = deadline
+            .saturating_duration_since(self.state.created_at)
+            .as_nanos();
+        let nanos = u64::try_from(nanos).unwrap_or(u64::MAX - 1);
+        self.state.deadline.store(nanos, Ordering::SeqCst);
+    }
+
+    /// Withdraws any cancellation request and deadline, so that the
+    /// runtime can do work for a new revision.
+    pub(crate) fn reset(&self) {
+        self.state.canceled.store(false, Ordering::SeqCst);
+        self.state.deadline.store(u64::MAX, Ordering::SeqCst);
+    }
+
+    /// True if `cancel` was called or the deadline has passed.
+    pub fn is_canceled(&self) -> bool {
+        if self.state.canceled.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let deadline = self.state.deadline.load(Ordering::SeqCst);
+        deadline != u64::MAX && self.state.created_at.elapsed().as_nanos() >= u128::from(deadline)
+    }
+}
@@ -1,7 +1,9 @@
 use crate::runtime::ActiveQuery;
 use crate::runtime::ChangedAt;
+use crate::runtime::MemoMeta;
 use crate::runtime::Revision;
 use crate::Database;
+use std::cell::Cell;
 use std::cell::Ref;
 use std::cell::RefCell;
 
@@ -17,20 +19,30 @@ pub(super) struct LocalState<DB: Database> {
     /// Unwinding note: pushes onto this vector must be popped -- even
     /// during unwinding.
     query_stack: RefCell<Vec<ActiveQuery<DB>>>,
+
+    /// True once the query stack has been reported for the panic that
+    /// is currently unwinding; reset whenever a query is pushed.
+    panic_reported: Cell<bool>,
 }
 
 impl<DB: Database> Default for LocalState<DB> {
     fn default() -> Self {
         LocalState {
             query_stack: Default::default(),
+            panic_reported: Cell::new(false),
         }
     }
 }
 
 impl<DB: Database> LocalState<DB> {
-    pub(super) fn push_query(&self, database_key: &DB::DatabaseKey) -> ActiveQueryGuard<'_, DB> {
+    pub(super) fn push_query(
+        &self,
+        database_key: &DB::DatabaseKey,
+        track_inputs: bool,
+    ) -> ActiveQueryGuard<'_, DB> {
+        self.panic_reported.set(false);
         let mut query_stack = self.query_stack.borrow_mut();
-        query_stack.push(ActiveQuery::new(database_key.clone()));
+        query_stack.push(ActiveQuery::new(database_key.clone(), track_inputs));
         ActiveQueryGuard {
             local_state: self,
             push_len: query_stack.len(),
@@ -46,6 +58,13 @@ impl<DB: Database> LocalState<DB> {
         self.query_stack.borrow()
     }
 
+    /// Returns true if the query stack should be reported for a panic
+    /// that is unwinding through the active query, i.e. if it has not
+    /// been reported by a query above it already.
+    pub(super) fn report_panic(&self) -> bool {
+        !self.panic_reported.replace(true)
+    }
+
     pub(super) fn query_in_progress(&self) -> bool {
         !self.query_stack.borrow().is_empty()
     }
@@ -63,6 +82,13 @@ impl<DB: Database> LocalState<DB> {
         }
     }
 
+    pub(super) fn attach_meta(&self, meta: MemoMeta) {
+        match self.query_stack.borrow_mut().last_mut() {
+            Some(top_query) => top_query.meta = Some(meta),
+            None => panic!("`attach_meta` invoked outside of a query computation"),
+        }
+    }
+
     pub(super) fn report_untracked_read(&self, current_revision: Revision) {
         if let Some(top_query) = self.query_stack.borrow_mut().last_mut() {
             top_query.add_untracked_read(current_revision);
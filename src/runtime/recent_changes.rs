@@ -0,0 +1,59 @@
+use crate::runtime::Revision;
+use crate::Database;
+use std::collections::VecDeque;
+
+/// For each of the most recent revisions, the derived values that
+/// were computed in that revision for the first time, or recomputed
+/// and found to differ from their previous value. Disabled (capacity
+/// zero) by default.
+pub(super) struct RecentChanges<DB: Database> {
+    capacity: usize,
+    revisions: VecDeque<(Revision, Vec<DB::DatabaseKey>)>,
+}
+
+impl<DB: Database> Default for RecentChanges<DB> {
+    fn default() -> Self {
+        RecentChanges {
+            capacity: 0,
+            revisions: VecDeque::new(),
+        }
+    }
+}
+
+impl<DB: Database> RecentChanges<DB> {
+    pub(super) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    pub(super) fn record(&mut self, revision: Revision, database_key: &DB::DatabaseKey) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        match self.revisions.back_mut() {
+            Some((last, database_keys)) if *last == revision => {
+                database_keys.push(database_key.clone());
+            }
+            _ => {
+                self.revisions
+                    .push_back((revision, vec![database_key.clone()]));
+                self.truncate();
+            }
+        }
+    }
+
+    pub(super) fn changes_in(&self, revision: Revision) -> Vec<DB::DatabaseKey> {
+        self.revisions
+            .iter()
+            .find(|(r, _)| *r == revision)
+            .map(|(_, database_keys)| database_keys.clone())
+            .unwrap_or_default()
+    }
+
+    fn truncate(&mut self) {
+        while self.revisions.len() > self.capacity {
+            self.revisions.pop_front();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::runtime::Revision;
+use crate::Database;
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// A bounded log of the most recent revisions. Disabled (capacity
+/// zero) by default.
+pub(super) struct RevisionHistory<DB: Database> {
+    capacity: usize,
+    records: VecDeque<RevisionRecord<DB>>,
+}
+
+impl<DB: Database> Default for RevisionHistory<DB> {
+    fn default() -> Self {
+        RevisionHistory {
+            capacity: 0,
+            records: VecDeque::new(),
+        }
+    }
+}
+
+impl<DB: Database> RevisionHistory<DB> {
+    pub(super) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    pub(super) fn record(&mut self, record: RevisionRecord<DB>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.records.push_back(record);
+        self.truncate();
+    }
+
+    pub(super) fn records(&self) -> Vec<RevisionRecord<DB>> {
+        self.records.iter().cloned().collect()
+    }
+
+    fn truncate(&mut self) {
+        while self.records.len() > self.capacity {
+            self.records.pop_front();
+        }
+    }
+}
+
+/// Describes a single revision increment; see
+/// [`Runtime::revision_history`](struct.Runtime.html#method.revision_history).
+pub struct RevisionRecord<DB: Database> {
+    /// The revision that was created.
+    pub revision: Revision,
+
+    /// When the revision was created.
+    pub timestamp: SystemTime,
+
+    /// The input whose change triggered the new revision (the first
+    /// one, for `set_many`), or `None` if it was created by an
+    /// explicit call to `next_revision` or by `retain`.
+    pub changed_input: Option<DB::DatabaseKey>,
+
+    /// True if the input was set via `set_constant`.
+    pub constant: bool,
+}
+
+impl<DB: Database> Clone for RevisionRecord<DB> {
+    fn clone(&self) -> Self {
+        RevisionRecord {
+            revision: self.revision,
+            timestamp: self.timestamp,
+            changed_input: self.changed_input.clone(),
+            constant: self.constant,
+        }
+    }
+}
+
+impl<DB: Database> std::fmt::Debug for RevisionRecord<DB> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("RevisionRecord")
+            .field("revision", &self.revision)
+            .field("timestamp", &self.timestamp)
+            .field("changed_input", &self.changed_input)
+            .field("constant", &self.constant)
+            .finish()
+    }
+}
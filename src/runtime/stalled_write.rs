@@ -0,0 +1,53 @@
+use crate::runtime::RuntimeId;
+use std::backtrace::Backtrace;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Callback installed via `Runtime::set_stalled_write_handler`.
+pub(super) type StalledWriteHandler = Arc<dyn Fn(&StalledWrite) + Send + Sync>;
+
+/// Describes a write that has been waiting for snapshots to be
+/// released for longer than the configured timeout; see
+/// [`Runtime::set_stalled_write_handler`](struct.Runtime.html#method.set_stalled_write_handler).
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct StalledWrite {
+    /// How long the write has been waiting so far.
+    pub waited: Duration,
+
+    /// The snapshots that were alive when the handler was invoked;
+    /// at least one of them is keeping the write from proceeding.
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
+/// Information about a live snapshot, for diagnosing stalled writes.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SnapshotInfo {
+    /// The id of the snapshot's runtime.
+    pub runtime_id: RuntimeId,
+
+    /// When the snapshot was created.
+    pub created_at: Instant,
+
+    /// Where the snapshot was created. Only captured in debug builds,
+    /// and only if backtraces are enabled (e.g., via
+    /// `RUST_BACKTRACE=1`); see `std::backtrace::Backtrace::capture`.
+    pub backtrace: Arc<Backtrace>,
+}
+
+impl SnapshotInfo {
+    pub(super) fn new(runtime_id: RuntimeId) -> Self {
+        let backtrace = if cfg!(debug_assertions) {
+            Backtrace::capture()
+        } else {
+            Backtrace::disabled()
+        };
+
+        SnapshotInfo {
+            runtime_id,
+            created_at: Instant::now(),
+            backtrace: Arc::new(backtrace),
+        }
+    }
+}
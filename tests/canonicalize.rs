@@ -0,0 +1,84 @@
+//! Test that `#[salsa::canonicalize]` causes equivalent keys to share
+//! a single memoized value.
+
+use std::cell::Cell;
+
+#[salsa::query_group(CanonicalizeStorage)]
+trait Canonicalize: CanonicalizeCounter {
+    #[salsa::input]
+    fn file_text(&self, path: String) -> String;
+
+    #[salsa::canonicalize(normalize_path)]
+    fn file_len(&self, path: String) -> usize;
+
+    #[salsa::canonicalize(normalize_pair)]
+    fn pair(&self, a: u32, b: u32) -> u32;
+}
+
+trait CanonicalizeCounter: salsa::Database {
+    fn increment(&self);
+}
+
+fn normalize_path(path: String) -> String {
+    path.trim_start_matches("./").to_string()
+}
+
+fn normalize_pair((a, b): (u32, u32)) -> (u32, u32) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn file_len(db: &impl Canonicalize, path: String) -> usize {
+    db.increment();
+    db.file_text(path).len()
+}
+
+fn pair(db: &impl Canonicalize, a: u32, b: u32) -> u32 {
+    db.increment();
+    a * 10 + b
+}
+
+#[salsa::database(CanonicalizeStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+    counter: Cell<usize>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+impl CanonicalizeCounter for Database {
+    fn increment(&self) {
+        self.counter.set(self.counter.get() + 1);
+    }
+}
+
+#[test]
+fn equivalent_keys_share_memo() {
+    let mut db = Database::default();
+    db.set_file_text("a.rs".to_string(), "hello".to_string());
+
+    assert_eq!(db.file_len("a.rs".to_string()), 5);
+    assert_eq!(db.file_len("./a.rs".to_string()), 5);
+    assert_eq!(db.counter.get(), 1);
+
+    db.set_file_text("a.rs".to_string(), "hi".to_string());
+    assert_eq!(db.file_len("./a.rs".to_string()), 2);
+    assert_eq!(db.file_len("a.rs".to_string()), 2);
+    assert_eq!(db.counter.get(), 2);
+}
+
+#[test]
+fn canonicalize_tuple_key() {
+    let db = Database::default();
+    assert_eq!(db.pair(1, 2), 12);
+    assert_eq!(db.pair(2, 1), 12);
+    assert_eq!(db.counter.get(), 1);
+}
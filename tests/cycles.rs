@@ -19,6 +19,27 @@ trait Database: salsa::Database {
     fn volatile_a(&self) -> ();
     #[salsa::volatile]
     fn volatile_b(&self) -> ();
+
+    // `cycle_length` and `try_cycle` form a cycle, which `try_cycle`
+    // observes via `try_get`
+    fn cycle_length(&self) -> usize;
+    fn try_cycle(&self) -> usize;
+
+    #[salsa::input]
+    fn unset_input(&self) -> ();
+
+    // observes via `try_get` whether `unset_input` is set
+    fn is_input_set(&self) -> bool;
+
+    // `recover_a` and `recover_b` form a cycle, which is resolved by
+    // the fallback value of `recover_a`
+    #[salsa::recovery(recover_a_fallback)]
+    fn recover_a(&self) -> usize;
+    fn recover_b(&self) -> usize;
+
+    // `panicky` panics, which `try_panicky` observes via `try_get`
+    fn panicky(&self) -> ();
+    fn try_panicky(&self) -> bool;
 }
 
 fn memoized_a(db: &impl Database) -> () {
@@ -37,6 +58,50 @@ fn volatile_b(db: &impl Database) -> () {
     db.volatile_a()
 }
 
+fn cycle_length(db: &impl Database) -> usize {
+    db.try_cycle()
+}
+
+fn try_cycle<DB>(db: &DB) -> usize
+where
+    DB: Database + salsa::plumbing::HasQueryGroup<GroupStruct>,
+{
+    match db.query(CycleLengthQuery).try_get(()) {
+        Err(salsa::QueryError::Cycle { cycle }) => cycle.len(),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+fn is_input_set<DB>(db: &DB) -> bool
+where
+    DB: Database + salsa::plumbing::HasQueryGroup<GroupStruct>,
+{
+    db.query(UnsetInputQuery).try_get(()).is_ok()
+}
+
+fn recover_a(db: &impl Database) -> usize {
+    db.recover_b() + 1
+}
+
+fn recover_a_fallback<DB: Database>(_db: &DB, cycle: &[DB::DatabaseKey], _key: &()) -> usize {
+    cycle.len()
+}
+
+fn recover_b(db: &impl Database) -> usize {
+    db.recover_a() * 10
+}
+
+fn panicky(_db: &impl Database) {
+    panic!("panicky failed")
+}
+
+fn try_panicky<DB>(db: &DB) -> bool
+where
+    DB: Database + salsa::plumbing::HasQueryGroup<GroupStruct>,
+{
+    db.query(PanickyQuery).try_get(()).is_err()
+}
+
 #[test]
 #[should_panic(expected = "cycle detected")]
 fn cycle_memoized() {
@@ -50,3 +115,74 @@ fn cycle_volatile() {
     let query = DatabaseImpl::default();
     query.volatile_a();
 }
+
+#[test]
+fn cycle_try_get() {
+    let query = DatabaseImpl::default();
+    assert_eq!(query.cycle_length(), 2);
+}
+
+#[test]
+fn unset_input_try_get() {
+    use salsa::Database as _;
+
+    let query = DatabaseImpl::default();
+    match query.query(UnsetInputQuery).try_get(()) {
+        Err(err @ salsa::QueryError::UnsetInput { .. }) => {
+            assert!(err.to_string().starts_with("no value set for"));
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn unset_input_try_get_is_tracked() {
+    let mut query = DatabaseImpl::default();
+    assert!(!query.is_input_set());
+
+    query.set_unset_input(());
+    assert!(query.is_input_set());
+}
+
+#[test]
+fn cycle_recovery() {
+    let query = DatabaseImpl::default();
+    assert_eq!(query.recover_a(), 21);
+    assert_eq!(query.recover_b(), 20);
+}
+
+#[test]
+fn panicked_try_get() {
+    use salsa::Database as _;
+
+    let query = DatabaseImpl::default();
+    match query.query(PanickyQuery).try_get(()) {
+        Err(err @ salsa::QueryError::Panicked { .. }) => {
+            assert!(err.panic_message().unwrap().starts_with("panicky failed"));
+            assert!(err.to_string().contains("panicky failed"));
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    // A query that observed the panic is re-executed in a new revision.
+    assert!(query.try_panicky());
+    query.salsa_runtime().next_revision();
+    assert!(query.try_panicky());
+    assert_eq!(query.query(TryPanickyQuery).execution_stats().executions, 2);
+}
+
+#[test]
+fn canceled_try_get() {
+    use salsa::Database as _;
+
+    let mut query = DatabaseImpl::default();
+    query.salsa_runtime().cancellation_token().cancel();
+    match query.query(RecoverAQuery).try_get(()) {
+        Err(salsa::QueryError::Canceled) => {}
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    // The cancellation does not outlive the revision.
+    query.set_unset_input(());
+    assert_eq!(query.query(RecoverAQuery).try_get(()).unwrap(), 21);
+}
@@ -0,0 +1,38 @@
+//! Test the execution statistics of derived queries.
+
+use salsa::Database as _;
+
+#[salsa::query_group(FibonacciStorage)]
+trait Fibonacci: salsa::Database {
+    fn fibonacci(&self, n: u32) -> u64;
+}
+
+fn fibonacci(db: &impl Fibonacci, n: u32) -> u64 {
+    if n < 2 {
+        u64::from(n)
+    } else {
+        db.fibonacci(n - 1) + db.fibonacci(n - 2)
+    }
+}
+
+#[salsa::database(FibonacciStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn recursion_is_not_concurrency() {
+    let db = Database::default();
+    assert_eq!(db.fibonacci(10), 55);
+
+    let stats = db.query(FibonacciQuery).execution_stats();
+    assert_eq!(stats.executions, 11);
+    assert_eq!(stats.peak_concurrency, 1);
+}
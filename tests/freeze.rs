@@ -0,0 +1,83 @@
+//! Test that a frozen database refuses to create new revisions.
+
+use salsa::{Database, ParallelDatabase, Snapshot};
+
+#[salsa::query_group(FreezeStorage)]
+trait FreezeDatabase: salsa::Database {
+    #[salsa::input]
+    fn input(&self) -> u32;
+
+    fn double(&self) -> u32;
+}
+
+fn double(db: &impl FreezeDatabase) -> u32 {
+    db.input() * 2
+}
+
+#[salsa::database(FreezeStorage)]
+#[derive(Default)]
+struct DatabaseStruct {
+    runtime: salsa::Runtime<DatabaseStruct>,
+}
+
+impl salsa::Database for DatabaseStruct {
+    fn salsa_runtime(&self) -> &salsa::Runtime<DatabaseStruct> {
+        &self.runtime
+    }
+}
+
+impl salsa::ParallelDatabase for DatabaseStruct {
+    fn snapshot(&self) -> Snapshot<Self> {
+        Snapshot::new(DatabaseStruct {
+            runtime: self.runtime.snapshot(self),
+        })
+    }
+}
+
+#[test]
+fn reads_while_frozen() {
+    let mut db = DatabaseStruct::default();
+    db.set_input(1);
+
+    let guard = db.salsa_runtime().freeze();
+    assert!(db.salsa_runtime().is_frozen());
+    assert_eq!(db.double(), 2);
+    drop(guard);
+
+    assert!(!db.salsa_runtime().is_frozen());
+    db.set_input(2);
+    assert_eq!(db.double(), 4);
+}
+
+#[test]
+#[should_panic(expected = "while it is frozen")]
+fn set_while_frozen() {
+    let mut db = DatabaseStruct::default();
+    db.set_input(1);
+
+    let _guard = db.salsa_runtime().freeze();
+    db.set_input(2);
+}
+
+#[test]
+#[should_panic(expected = "while it is frozen")]
+fn set_while_snapshot_frozen() {
+    let mut db = DatabaseStruct::default();
+    db.set_input(1);
+
+    let guard = {
+        let snapshot = db.snapshot();
+        snapshot.salsa_runtime().freeze()
+    };
+    db.set_input(2);
+    drop(guard);
+}
+
+#[test]
+#[should_panic(expected = "while it is frozen")]
+fn next_revision_while_frozen() {
+    let db = DatabaseStruct::default();
+
+    let _guard = db.salsa_runtime().freeze();
+    db.salsa_runtime().next_revision();
+}
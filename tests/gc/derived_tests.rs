@@ -152,3 +152,71 @@ fn compute_all() {
         MaxQuery => (()),
     }
 }
+
+#[test]
+fn entries_in_insertion_order() {
+    let mut db = db::DatabaseImpl::default();
+
+    db.set_use_triangular(5, false);
+    db.set_use_triangular(1, false);
+    db.compute(5);
+
+    let keys = |entries: Vec<salsa::debug::TableEntry<usize, usize>>| {
+        entries.into_iter().map(|e| e.key).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        keys(db.query(FibonacciQuery).entries()),
+        vec![5, 4, 3, 2, 1, 0]
+    );
+    assert_eq!(
+        keys(db.query(FibonacciQuery).entries_sorted_by_key()),
+        vec![0, 1, 2, 3, 4, 5]
+    );
+
+    let inputs = db.query(UseTriangularQuery).entries::<Vec<_>>();
+    assert_eq!(
+        inputs.into_iter().map(|e| e.key).collect::<Vec<_>>(),
+        vec![5, 1]
+    );
+}
+
+#[test]
+fn entries_keep_order_after_discard() {
+    let mut db = db::DatabaseImpl::default();
+
+    db.set_use_triangular(5, false);
+    db.compute(5);
+
+    // Unmocking discards the memo for `fibonacci(2)`.
+    db.query_mut(FibonacciQuery).mock(2, 1);
+    db.query_mut(FibonacciQuery).unmock(2);
+
+    let entries = db.query(FibonacciQuery).entries::<Vec<_>>();
+    assert_eq!(
+        entries.into_iter().map(|e| e.key).collect::<Vec<_>>(),
+        vec![5, 4, 3, 1, 0]
+    );
+}
+
+#[test]
+fn map_entries_borrows() {
+    let mut db = db::DatabaseImpl::default();
+
+    db.set_use_triangular(5, false);
+    db.compute(5);
+
+    let fib = db
+        .query(FibonacciQuery)
+        .map_entries(|&key, value| (key, value.copied()));
+    assert_eq!(
+        fib,
+        vec![
+            (5, Some(5)),
+            (4, Some(3)),
+            (3, Some(2)),
+            (2, Some(1)),
+            (1, Some(1)),
+            (0, Some(0)),
+        ]
+    );
+}
@@ -0,0 +1,42 @@
+//! Test that query groups with the same name can be combined in one
+//! database by importing one of them under another name.
+
+mod a {
+    #[salsa::query_group(Group)]
+    pub trait A: salsa::Database {
+        #[salsa::input]
+        fn x(&self) -> u32;
+    }
+}
+
+mod b {
+    #[salsa::query_group(Group)]
+    pub trait B: salsa::Database {
+        #[salsa::input]
+        fn y(&self) -> u32;
+    }
+}
+
+use a::A;
+use b::Group as BGroup;
+use b::B;
+
+#[salsa::database(a::Group, BGroup)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn groups_with_same_name() {
+    let mut db = Database::default();
+    db.set_x(1);
+    db.set_y(2);
+    assert_eq!(db.x() + db.y(), 3);
+}
@@ -1,6 +1,8 @@
 //! Test that you can implement a query using a `dyn Trait` setup.
 
-use salsa::InternId;
+use salsa::debug::DebugQueryTable;
+use salsa::{Database as _, InternId};
+use std::borrow::Cow;
 
 #[salsa::database(InternStorage)]
 #[derive(Default)]
@@ -32,6 +34,20 @@ trait Intern {
 
     #[salsa::interned]
     fn intern_key(&self, x: String) -> InternKey;
+
+    #[salsa::interned]
+    fn intern_constant_hash(&self, x: ConstantHash) -> InternId;
+
+    #[salsa::interned]
+    fn intern_static(&self, x: &'static str, y: Cow<'static, str>) -> InternId;
+}
+
+/// A key type whose `Hash` impl maps everything to the same hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstantHash(u32);
+
+impl std::hash::Hash for ConstantHash {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -94,3 +110,127 @@ fn test_intern_key() {
     assert_eq!(format!("foo"), db.lookup_intern_key(foo0));
     assert_eq!(format!("bar"), db.lookup_intern_key(bar0));
 }
+
+#[test]
+fn test_intern_many() {
+    let db = Database::default();
+    let foo0 = db.intern1("foo".to_string());
+
+    let ids = db.query(Intern1Query).intern_many(vec![
+        "bar".to_string(),
+        "foo".to_string(),
+        "bar".to_string(),
+        "baz".to_string(),
+    ]);
+
+    assert_eq!(ids.len(), 4);
+    assert_eq!(ids[1], foo0);
+    assert_eq!(ids[0], ids[2]);
+    assert_ne!(ids[0], ids[3]);
+    assert_ne!(ids[0], foo0);
+
+    assert_eq!(ids[0], db.intern1("bar".to_string()));
+    assert_eq!(format!("baz"), db.lookup_intern1(ids[3]));
+}
+
+#[test]
+fn test_intern_many_duplicates() {
+    let db = Database::default();
+    let ids = db.query(Intern1Query).intern_many(vec![
+        "foo".to_string(),
+        "bar".to_string(),
+        "foo".to_string(),
+        "foo".to_string(),
+    ]);
+    assert_eq!(ids[0], ids[2]);
+    assert_eq!(ids[0], ids[3]);
+    assert_ne!(ids[0], ids[1]);
+
+    // Each distinct key is interned once, in order of first occurrence.
+    let stats = db.query(Intern1Query).intern_stats();
+    assert_eq!(stats.len, 2);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.hits, 0);
+    let keys: Vec<_> = db
+        .query(Intern1Query)
+        .entries::<Vec<_>>()
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    assert_eq!(keys, vec!["foo".to_string(), "bar".to_string()]);
+}
+
+#[test]
+fn test_intern_stats() {
+    let db = Database::default();
+    db.intern1("foo".to_string());
+    db.intern1("bar".to_string());
+    db.intern1("foo".to_string());
+    db.query(Intern1Query)
+        .intern_many(vec!["foo".to_string(), "baz".to_string()]);
+
+    let stats = db.query(Intern1Query).intern_stats();
+    assert_eq!(stats.len, 3);
+    assert_eq!(stats.free, 0);
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.misses, 3);
+    assert_eq!(stats.hash_collisions, 0);
+    assert!(stats.shallow_bytes > 0);
+
+    for i in 0..4 {
+        db.intern_constant_hash(ConstantHash(i));
+    }
+    let stats = db.query(InternConstantHashQuery).intern_stats();
+    assert_eq!(stats.len, 4);
+    assert_eq!(stats.hash_collisions, 3);
+}
+
+#[test]
+fn test_map_entries() {
+    let db = Database::default();
+    let foo = db.intern1("foo".to_string());
+    let bar = db.intern1("bar".to_string());
+
+    let lengths = db
+        .query(Intern1Query)
+        .map_entries(|key, value| (key.len(), value.copied()));
+    assert_eq!(lengths, vec![(3, Some(foo)), (3, Some(bar))]);
+
+    let lookups = db
+        .query(Intern1LookupQuery)
+        .map_entries(|&key, value| (key, value.cloned()));
+    assert_eq!(
+        lookups,
+        vec![
+            (foo, Some("foo".to_string())),
+            (bar, Some("bar".to_string()))
+        ]
+    );
+}
+
+#[test]
+fn test_with_value() {
+    let db = Database::default();
+    let foo = db.intern1("foo".to_string());
+    let pair = db.intern2("x".to_string(), "yy".to_string());
+
+    let len = db.query(Intern1LookupQuery).with_value(foo, |s| s.len());
+    assert_eq!(len, 3);
+
+    let same = db
+        .query(Intern2LookupQuery)
+        .with_value(pair, |(x, y)| x == "x" && y == "yy");
+    assert!(same);
+}
+
+#[test]
+fn test_intern_static() {
+    let db = Database::default();
+    let foo = db.intern_static("foo", Cow::Borrowed("bar"));
+    assert_eq!(db.intern_static("foo", Cow::Owned("bar".to_string())), foo);
+    assert_ne!(db.intern_static("foo", Cow::Borrowed("baz")), foo);
+
+    let (x, y) = db.lookup_intern_static(foo);
+    assert_eq!(x, "foo");
+    assert_eq!(y, "bar");
+}
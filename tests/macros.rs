@@ -4,6 +4,7 @@ trait MyDatabase: salsa::Database {
     fn my_query(&self, key: ()) -> ();
 }
 
+#[allow(dead_code)]
 mod another_module {
     pub(crate) fn another_name(_: &impl crate::MyDatabase, (): ()) -> () {}
 }
@@ -0,0 +1,62 @@
+//! Test that query functions can attach metadata to their memos.
+
+use salsa::Database as _;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Executions(u32);
+
+#[salsa::query_group(MetaStorage)]
+trait Meta: salsa::Database {
+    #[salsa::input]
+    fn input(&self) -> u32;
+
+    fn length(&self, text: String) -> usize;
+}
+
+fn length(db: &impl Meta, text: String) -> usize {
+    db.salsa_runtime().attach_meta(Executions(db.input()));
+    text.len()
+}
+
+#[salsa::database(MetaStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn fetch_meta() {
+    let mut db = Database::default();
+    db.set_input(1);
+
+    let meta = db
+        .query(LengthQuery)
+        .fetch_meta::<Executions>("foo".to_string());
+    assert_eq!(meta.as_deref(), Some(&Executions(1)));
+    assert_eq!(db.length("foo".to_string()), 3);
+
+    // Re-execution replaces the metadata, even though the value is
+    // backdated.
+    db.set_input(2);
+    let meta = db
+        .query(LengthQuery)
+        .fetch_meta::<Executions>("foo".to_string());
+    assert_eq!(meta.as_deref(), Some(&Executions(2)));
+
+    // Metadata of another type is not found.
+    let meta = db.query(LengthQuery).fetch_meta::<u32>("foo".to_string());
+    assert_eq!(meta, None);
+}
+
+#[test]
+#[should_panic(expected = "outside of a query computation")]
+fn attach_meta_outside_query() {
+    let db = Database::default();
+    db.salsa_runtime().attach_meta(Executions(0));
+}
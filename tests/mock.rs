@@ -0,0 +1,81 @@
+//! Test that derived query results can be mocked and unmocked.
+
+use salsa::Database as _;
+use std::cell::Cell;
+
+#[salsa::query_group(MockStorage)]
+trait Mock: salsa::Database + Counter {
+    #[salsa::input]
+    fn input(&self) -> u32;
+
+    fn expensive(&self, key: u32) -> u32;
+
+    fn caller(&self, key: u32) -> u32;
+}
+
+trait Counter {
+    fn increment(&self);
+}
+
+fn expensive(db: &impl Mock, key: u32) -> u32 {
+    db.increment();
+    db.input() + key
+}
+
+fn caller(db: &impl Mock, key: u32) -> u32 {
+    db.expensive(key) * 10
+}
+
+#[salsa::database(MockStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+    executions: Cell<usize>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+impl Counter for Database {
+    fn increment(&self) {
+        self.executions.set(self.executions.get() + 1);
+    }
+}
+
+#[test]
+fn mock_bypasses_execution() {
+    let mut db = Database::default();
+    db.set_input(1);
+
+    db.query_mut(ExpensiveQuery).mock(2, 100);
+    assert_eq!(db.caller(2), 1000);
+    assert_eq!(db.executions.get(), 0);
+
+    // The mock stays valid across unrelated changes.
+    db.set_input(2);
+    assert_eq!(db.caller(2), 1000);
+    assert_eq!(db.executions.get(), 0);
+
+    // Other keys are computed as usual.
+    assert_eq!(db.caller(3), 50);
+    assert_eq!(db.executions.get(), 1);
+}
+
+#[test]
+fn unmock_restores_query() {
+    let mut db = Database::default();
+    db.set_input(1);
+
+    // Memoize the real value before mocking it.
+    assert_eq!(db.caller(2), 30);
+
+    db.query_mut(ExpensiveQuery).mock(2, 100);
+    assert_eq!(db.caller(2), 1000);
+
+    db.query_mut(ExpensiveQuery).unmock(2);
+    assert_eq!(db.caller(2), 30);
+    assert_eq!(db.executions.get(), 2);
+}
@@ -0,0 +1,79 @@
+//! Test that a one-shot runtime memoizes within a revision but does
+//! not track dependencies across revisions.
+
+use std::cell::Cell;
+
+#[salsa::query_group(OneShotStorage)]
+trait OneShot: salsa::Database + Counter {
+    #[salsa::input]
+    fn input(&self, key: u32) -> u32;
+
+    fn double(&self, key: u32) -> u32;
+}
+
+trait Counter {
+    fn increment(&self);
+}
+
+fn double(db: &impl OneShot, key: u32) -> u32 {
+    db.increment();
+    db.input(key) * 2
+}
+
+#[salsa::database(OneShotStorage)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+    executions: Cell<usize>,
+}
+
+impl Database {
+    fn new(runtime: salsa::Runtime<Database>) -> Self {
+        Database {
+            runtime,
+            executions: Cell::new(0),
+        }
+    }
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+impl Counter for Database {
+    fn increment(&self) {
+        self.executions.set(self.executions.get() + 1);
+    }
+}
+
+#[test]
+fn one_shot_memoizes_within_revision() {
+    let mut db = Database::new(salsa::Runtime::new_one_shot());
+    assert!(db.runtime.is_one_shot());
+    db.set_input(1, 10);
+    db.set_input(2, 20);
+
+    assert_eq!(db.double(1), 20);
+    assert_eq!(db.double(1), 20);
+    assert_eq!(db.executions.get(), 1);
+
+    // Changing an unrelated input still re-executes, since no
+    // dependencies were recorded.
+    db.set_input(2, 21);
+    assert_eq!(db.double(1), 20);
+    assert_eq!(db.executions.get(), 2);
+}
+
+#[test]
+fn default_runtime_tracks_dependencies() {
+    let mut db = Database::new(salsa::Runtime::new());
+    assert!(!db.runtime.is_one_shot());
+    db.set_input(1, 10);
+    db.set_input(2, 20);
+
+    assert_eq!(db.double(1), 20);
+    db.set_input(2, 21);
+    assert_eq!(db.double(1), 20);
+    assert_eq!(db.executions.get(), 1);
+}
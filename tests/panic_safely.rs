@@ -1,5 +1,6 @@
 use salsa::{Database, ParallelDatabase, Snapshot};
 use std::panic::{self, AssertUnwindSafe};
+use std::process::Command;
 use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
 
 #[salsa::query_group(PanicSafelyStruct)]
@@ -10,6 +11,10 @@ trait PanicSafelyDatabase: salsa::Database {
     fn panic_safely(&self) -> ();
 
     fn outer(&self) -> ();
+
+    fn nested(&self) -> ();
+
+    fn static_panic(&self) -> ();
 }
 
 fn panic_safely(db: &impl PanicSafelyDatabase) -> () {
@@ -23,6 +28,14 @@ fn outer(db: &impl PanicSafelyDatabase) -> () {
     db.panic_safely();
 }
 
+fn nested(db: &impl PanicSafelyDatabase) {
+    db.panic_safely();
+}
+
+fn static_panic(_db: &impl PanicSafelyDatabase) {
+    panic!("static panic message");
+}
+
 #[salsa::database(PanicSafelyStruct)]
 #[derive(Default)]
 struct DatabaseStruct {
@@ -78,6 +91,61 @@ fn should_panic_safely() {
     }
 }
 
+#[test]
+fn panic_payload_is_unchanged() {
+    let db = DatabaseStruct::default();
+
+    let payload = panic::catch_unwind(AssertUnwindSafe(|| db.static_panic())).unwrap_err();
+    assert_eq!(
+        payload.downcast_ref::<&'static str>(),
+        Some(&"static panic message")
+    );
+}
+
+#[test]
+fn panic_reports_query_stack() {
+    // The stack is printed to stderr, so run this test again in a child
+    // process and inspect its output.
+    const CHILD: &str = "SALSA_PANIC_REPORT_CHILD";
+
+    if std::env::var_os(CHILD).is_some() {
+        let mut db = DatabaseStruct::default();
+        db.set_one(0);
+        eprintln!("runtime: {:?}", db.salsa_runtime().id());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| db.nested()));
+        assert!(result.is_err());
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .arg("panic_reports_query_stack")
+        .arg("--exact")
+        .arg("--nocapture")
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    let runtime_id = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("runtime: "))
+        .unwrap();
+    let header = format!("salsa query stack ({}):", runtime_id);
+    let stack = stderr.split(&header).collect::<Vec<_>>();
+    assert_eq!(stack.len(), 2, "{}", stderr);
+    assert!(stack[0].contains("assertion"), "{}", stderr);
+
+    let lines = stack[1]
+        .lines()
+        .skip(1)
+        .take_while(|line| line.starts_with("  "))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", stderr);
+    assert!(lines[0].trim().starts_with("0: ") && lines[0].contains("panic_safely"));
+    assert!(lines[1].trim().starts_with("1: ") && lines[1].contains("nested"));
+}
+
 #[test]
 fn storages_are_unwind_safe() {
     fn check_unwind_safe<T: std::panic::UnwindSafe>() {}
@@ -97,3 +165,20 @@ fn panics_clear_query_stack() {
     // revision should panic.
     assert_eq!(db.salsa_runtime().active_query(), None);
 }
+
+#[test]
+fn database_ids_are_distinct() {
+    let db1 = DatabaseStruct::default();
+    let db2 = DatabaseStruct::default();
+    assert_ne!(
+        db1.salsa_runtime().database_id(),
+        db2.salsa_runtime().database_id()
+    );
+
+    let snapshot = db1.snapshot();
+    assert_eq!(
+        snapshot.salsa_runtime().database_id(),
+        db1.salsa_runtime().database_id()
+    );
+    assert_ne!(snapshot.salsa_runtime().id(), db1.salsa_runtime().id());
+}
@@ -187,3 +187,35 @@ fn transitive_cancellation() {
 
     assert_eq!(thread1.join().unwrap(), 22);
 }
+
+/// Test that a snapshot can be canceled through its token, from
+/// another thread or by a deadline, without affecting other runtimes.
+#[test]
+fn cancellation_token() {
+    use salsa::Database;
+    use std::time::{Duration, Instant};
+
+    let db = ParDatabaseImpl::default();
+
+    let snapshot = db.snapshot();
+    assert!(!snapshot.salsa_runtime().is_canceled());
+    let token = snapshot.salsa_runtime().cancellation_token();
+    std::thread::spawn(move || token.cancel()).join().unwrap();
+    assert!(snapshot.salsa_runtime().is_canceled());
+    assert!(!snapshot.salsa_runtime().is_current_revision_canceled());
+    assert!(!db.salsa_runtime().is_canceled());
+
+    let expired = db.snapshot();
+    expired
+        .salsa_runtime()
+        .cancellation_token()
+        .cancel_at(Instant::now());
+    assert!(expired.salsa_runtime().is_canceled());
+
+    let pending = db.snapshot();
+    pending
+        .salsa_runtime()
+        .cancellation_token()
+        .cancel_at(Instant::now() + Duration::from_secs(3600));
+    assert!(!pending.salsa_runtime().is_canceled());
+}
@@ -63,3 +63,38 @@ fn in_par_get_set_cancellation() {
     let c = thread2.join().unwrap();
     assert_eq!(c, 2);
 }
+
+/// Check that `Snapshot::still_current` turns false once a write is
+/// pending, while `Snapshot::revision` stays fixed.
+#[test]
+fn snapshot_still_current() {
+    let mut db = ParDatabaseImpl::default();
+
+    db.set_input('a', 1);
+
+    let signal = Arc::new(Signal::default());
+
+    let thread1 = std::thread::spawn({
+        let db = db.snapshot();
+        let signal = signal.clone();
+        move || {
+            let revision = db.revision();
+            assert!(db.still_current());
+
+            signal.signal(1);
+
+            while db.still_current() {
+                std::thread::yield_now();
+            }
+
+            assert_eq!(db.revision(), revision);
+            revision
+        }
+    });
+
+    signal.wait_for(1);
+    db.set_input('a', 2);
+
+    let revision = thread1.join().unwrap();
+    assert!(db.salsa_runtime().current_revision() > revision);
+}
@@ -6,5 +6,6 @@ mod frozen;
 mod independent;
 mod race;
 mod signal;
+mod stalled_write;
 mod stress;
 mod true_parallel;
@@ -0,0 +1,52 @@
+use crate::setup::{ParDatabase, ParDatabaseImpl};
+use crate::signal::Signal;
+use parking_lot::Mutex;
+use salsa::{Database, ParallelDatabase};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Check that a write blocked by a snapshot that is never released
+/// reports the offending snapshot to the stalled-write handler.
+#[test]
+fn stalled_write_reports_snapshot() {
+    let mut db = ParDatabaseImpl::default();
+    db.set_input('a', 1);
+
+    let signal = Arc::new(Signal::default());
+    let reported = Arc::new(Mutex::new(vec![]));
+
+    db.salsa_runtime()
+        .set_stalled_write_handler(Duration::from_millis(10), {
+            let signal = signal.clone();
+            let reported = reported.clone();
+            move |stalled| {
+                assert!(stalled.waited >= Duration::from_millis(10));
+                reported
+                    .lock()
+                    .extend(stalled.snapshots.iter().map(|s| s.runtime_id));
+                signal.signal(2);
+            }
+        });
+
+    let thread1 = std::thread::spawn({
+        let db = db.snapshot();
+        let signal = signal.clone();
+        move || {
+            let id = db.salsa_runtime().id();
+            signal.signal(1);
+
+            // Hold the snapshot (ignoring cancellation) until the
+            // write has been reported as stalled.
+            signal.wait_for(2);
+            id
+        }
+    });
+
+    signal.wait_for(1);
+    db.set_input('a', 2);
+
+    let id = thread1.join().unwrap();
+    let reported = reported.lock();
+    assert!(!reported.is_empty());
+    assert!(reported.iter().all(|&r| r == id));
+}
@@ -1,5 +1,5 @@
-use crate::setup::{Knobs, ParDatabase, ParDatabaseImpl, WithValue};
-use salsa::ParallelDatabase;
+use crate::setup::{Knobs, ParDatabase, ParDatabaseImpl, SumQuery, WithValue};
+use salsa::{Database, ParallelDatabase};
 use std::panic::{self, AssertUnwindSafe};
 
 /// Test where two threads are executing sum. We show that they can
@@ -41,6 +41,10 @@ fn true_parallel_different_keys() {
 
     assert_eq!(thread1.join().unwrap(), 100);
     assert_eq!(thread2.join().unwrap(), 010);
+
+    let stats = db.query(SumQuery).execution_stats();
+    assert_eq!(stats.executions, 2);
+    assert_eq!(stats.peak_concurrency, 2);
 }
 
 /// Add a test that tries to trigger a conflict, where we fetch
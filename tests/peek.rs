@@ -0,0 +1,69 @@
+//! Test that `peek` returns memoized values without executing queries.
+
+use salsa::Database as _;
+
+#[salsa::query_group(PeekStorage)]
+trait Peek: salsa::Database {
+    #[salsa::input]
+    fn input(&self) -> u32;
+
+    fn double(&self) -> u32;
+
+    fn peek_double(&self) -> Option<u32>;
+}
+
+fn double(db: &impl Peek) -> u32 {
+    db.input() * 2
+}
+
+fn peek_double<DB>(db: &DB) -> Option<u32>
+where
+    DB: Peek + salsa::plumbing::HasQueryGroup<PeekStorage>,
+{
+    db.query(DoubleQuery).peek(())
+}
+
+#[salsa::database(PeekStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn peek() {
+    let mut db = Database::default();
+    db.set_input(1);
+    assert_eq!(db.query(DoubleQuery).peek(()), None);
+    assert_eq!(db.query(DoubleQuery).execution_stats().executions, 0);
+
+    assert_eq!(db.double(), 2);
+    assert_eq!(db.query(DoubleQuery).peek(()), Some(2));
+
+    // The stale value is returned, and the query is not re-executed.
+    db.set_input(2);
+    assert_eq!(db.query(DoubleQuery).peek(()), Some(2));
+    assert_eq!(db.query(DoubleQuery).execution_stats().executions, 1);
+
+    assert_eq!(db.double(), 4);
+    assert_eq!(db.query(DoubleQuery).peek(()), Some(4));
+}
+
+#[test]
+fn peek_within_query() {
+    let mut db = Database::default();
+    db.set_input(1);
+    assert_eq!(db.peek_double(), None);
+
+    // `peek_double` did not depend on `double`, but it is re-executed
+    // in a new revision anyway.
+    assert_eq!(db.double(), 2);
+    assert_eq!(db.peek_double(), None);
+    db.set_input(1);
+    assert_eq!(db.peek_double(), Some(2));
+}
@@ -0,0 +1,83 @@
+//! Test that `#[salsa::projection]` queries extract a piece of their
+//! parent and shield dependents from unrelated changes.
+
+use std::cell::Cell;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Item {
+    name: String,
+    body: String,
+}
+
+#[salsa::query_group(ProjectionStorage)]
+trait Projection: salsa::Database + Counter {
+    #[salsa::input]
+    fn item(&self, key: u32) -> Item;
+
+    #[salsa::projection(of = item, via = name_of)]
+    fn item_name(&self, key: u32) -> String;
+
+    fn shout(&self, key: u32) -> String;
+}
+
+trait Counter {
+    fn increment(&self);
+}
+
+fn name_of(item: &Item) -> String {
+    item.name.clone()
+}
+
+fn shout(db: &impl Projection, key: u32) -> String {
+    db.increment();
+    db.item_name(key).to_uppercase()
+}
+
+#[salsa::database(ProjectionStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+    executions: Cell<usize>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+impl Counter for Database {
+    fn increment(&self) {
+        self.executions.set(self.executions.get() + 1);
+    }
+}
+
+#[test]
+fn projection_is_a_firewall() {
+    let mut db = Database::default();
+    let item = |body: &str| Item {
+        name: "foo".to_string(),
+        body: body.to_string(),
+    };
+    db.set_item(0, item("one"));
+
+    assert_eq!(db.item_name(0), "foo");
+    assert_eq!(db.shout(0), "FOO");
+    assert_eq!(db.executions.get(), 1);
+
+    // Changing the body does not change the name, so `shout` is not
+    // re-executed.
+    db.set_item(0, item("two"));
+    assert_eq!(db.shout(0), "FOO");
+    assert_eq!(db.executions.get(), 1);
+
+    db.set_item(
+        0,
+        Item {
+            name: "bar".to_string(),
+            body: "two".to_string(),
+        },
+    );
+    assert_eq!(db.shout(0), "BAR");
+    assert_eq!(db.executions.get(), 2);
+}
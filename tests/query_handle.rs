@@ -0,0 +1,51 @@
+//! Test that a `QueryHandle` fetches and validates its query.
+
+use salsa::Database as _;
+
+#[salsa::query_group(QueryHandleStorage)]
+trait QueryHandleDatabase: salsa::Database {
+    #[salsa::input]
+    fn input1(&self) -> usize;
+
+    #[salsa::input]
+    fn input2(&self) -> usize;
+
+    fn max(&self) -> usize;
+}
+
+fn max(db: &impl QueryHandleDatabase) -> usize {
+    std::cmp::max(db.input1(), db.input2())
+}
+
+#[salsa::database(QueryHandleStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn query_handle() {
+    let mut db = Database::default();
+
+    db.set_input1(1);
+    db.set_input2(2);
+
+    let handle: salsa::QueryHandle<Database, MaxQuery> = salsa::QueryHandle::new(());
+    assert_eq!(format!("{:?}", handle), "MaxQuery(())");
+    assert_eq!(handle.fetch(&db), 2);
+    assert_eq!(db.query(MaxQuery).execution_stats().executions, 1);
+
+    let revision = db.salsa_runtime().current_revision();
+    assert!(!handle.maybe_changed_since(&db, revision));
+
+    db.set_input1(3);
+    assert!(handle.maybe_changed_since(&db, revision));
+    assert_eq!(handle.fetch(&db), 3);
+    assert_eq!(db.query(MaxQuery).execution_stats().executions, 2);
+}
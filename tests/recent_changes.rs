@@ -0,0 +1,84 @@
+//! Test that `Runtime::changes_in` lists the derived values that
+//! changed in recent revisions.
+
+use salsa::Database as _;
+
+#[salsa::query_group(RecentChangesStorage)]
+trait RecentChanges: salsa::Database {
+    #[salsa::input]
+    fn input1(&self) -> usize;
+
+    #[salsa::input]
+    fn input2(&self) -> usize;
+
+    fn max(&self) -> usize;
+
+    fn double_max(&self) -> usize;
+}
+
+fn max(db: &impl RecentChanges) -> usize {
+    std::cmp::max(db.input1(), db.input2())
+}
+
+fn double_max(db: &impl RecentChanges) -> usize {
+    db.max() * 2
+}
+
+#[salsa::database(RecentChangesStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+fn changes_in_current_revision(db: &Database) -> Vec<String> {
+    db.salsa_runtime()
+        .changes_in(db.salsa_runtime().current_revision())
+        .iter()
+        .map(|database_key| format!("{:?}", database_key))
+        .collect()
+}
+
+#[test]
+fn changes_in() {
+    let mut db = Database::default();
+
+    // Nothing is recorded until a capacity is configured.
+    db.set_input1(0);
+    db.set_input2(0);
+    db.max();
+    assert!(changes_in_current_revision(&db).is_empty());
+
+    db.salsa_runtime().set_recent_changes_capacity(1);
+
+    // Recomputed to the same value: backdated, not a change.
+    db.set_input1(0);
+    db.max();
+    let first = db.salsa_runtime().current_revision();
+    assert!(db.salsa_runtime().changes_in(first).is_empty());
+
+    // Computed for the first time: a change.
+    db.double_max();
+    let changes = changes_in_current_revision(&db);
+    assert_eq!(changes.len(), 1);
+    assert!(changes[0].contains("double_max"));
+
+    db.set_input1(44);
+    db.double_max();
+    let second = db.salsa_runtime().current_revision();
+    let changes = changes_in_current_revision(&db);
+    assert_eq!(changes.len(), 2);
+    assert!(changes[0].contains("max"));
+    assert!(changes[1].contains("double_max"));
+
+    // Only the most recent revision is retained.
+    db.set_input1(66);
+    db.max();
+    assert!(db.salsa_runtime().changes_in(second).is_empty());
+    assert_eq!(changes_in_current_revision(&db).len(), 1);
+}
@@ -0,0 +1,58 @@
+//! Test that a derived query's implementation can be replaced by a
+//! closure registered at runtime.
+
+use salsa::Database as _;
+
+#[salsa::query_group(RegisterImplStorage)]
+trait RegisterImpl: salsa::Database {
+    #[salsa::input]
+    fn input(&self) -> u32;
+
+    fn expensive(&self, key: u32) -> u32;
+
+    fn caller(&self, key: u32) -> u32;
+}
+
+fn expensive(db: &impl RegisterImpl, key: u32) -> u32 {
+    db.input() + key
+}
+
+fn caller(db: &impl RegisterImpl, key: u32) -> u32 {
+    db.expensive(key) * 10
+}
+
+#[salsa::database(RegisterImplStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn registered_impl_replaces_query_function() {
+    let mut db = Database::default();
+    db.query_mut(ExpensiveQuery)
+        .register_impl(|db: &Database, key| db.input() * key);
+    db.set_input(2);
+
+    assert_eq!(db.caller(3), 60);
+
+    // The registered implementation participates in dependency
+    // tracking like a normal query function.
+    db.set_input(3);
+    assert_eq!(db.caller(3), 90);
+}
+
+#[test]
+#[should_panic(expected = "after it has been executed")]
+fn register_impl_after_execution() {
+    let mut db = Database::default();
+    db.set_input(2);
+    db.expensive(1);
+    db.query_mut(ExpensiveQuery).register_impl(|_, key| key);
+}
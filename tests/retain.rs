@@ -0,0 +1,78 @@
+//! Test that `retain` removes inputs in a single revision.
+
+use salsa::debug::DebugQueryTable;
+use salsa::prelude::*;
+
+#[salsa::query_group(RetainStorage)]
+trait Retain: Database {
+    #[salsa::input]
+    fn input(&self, key: u32) -> u32;
+
+    /// Sums the inputs of `keys`, skipping keys that are not set.
+    fn sum_present(&self, keys: Vec<u32>) -> u32;
+}
+
+fn sum_present<DB>(db: &DB, keys: Vec<u32>) -> u32
+where
+    DB: Retain + salsa::plumbing::HasQueryGroup<RetainStorage>,
+{
+    let table = db.query(InputQuery);
+    keys.into_iter()
+        .filter_map(|key| table.try_get(key).ok())
+        .sum()
+}
+
+#[salsa::database(RetainStorage)]
+#[derive(Default)]
+struct DatabaseStruct {
+    runtime: Runtime<DatabaseStruct>,
+}
+
+impl Database for DatabaseStruct {
+    fn salsa_runtime(&self) -> &Runtime<DatabaseStruct> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn retain() {
+    let mut db = DatabaseStruct::default();
+    db.query_mut(InputQuery)
+        .set_many((0..10).map(|key| (key, key)));
+    assert_eq!(db.sum_present(vec![1, 7]), 8);
+
+    db.query_mut(InputQuery).retain(|&key| key < 5);
+    assert_eq!(db.query(InputQuery).entries::<Vec<_>>().len(), 5);
+    assert_eq!(db.sum_present(vec![1, 7]), 1);
+
+    db.set_input(7, 70);
+    assert_eq!(db.sum_present(vec![1, 7]), 71);
+
+    // Removing nothing does not create a revision.
+    let revision = db.salsa_runtime().current_revision();
+    db.query_mut(InputQuery).retain(|_| true);
+    assert_eq!(db.salsa_runtime().current_revision(), revision);
+}
+
+#[test]
+#[should_panic(expected = "previously marked as constant")]
+fn retain_constant() {
+    let mut db = DatabaseStruct::default();
+    db.query_mut(InputQuery).set_constant(0, 0);
+    db.query_mut(InputQuery).retain(|_| false);
+}
+
+#[test]
+fn retain_constant_removes_nothing() {
+    let mut db = DatabaseStruct::default();
+    db.query_mut(InputQuery).set(0, 0);
+    db.query_mut(InputQuery).set_constant(1, 1);
+    let revision = db.salsa_runtime().current_revision();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        db.query_mut(InputQuery).retain(|_| false);
+    }));
+    assert!(result.is_err());
+    assert_eq!(db.query(InputQuery).entries::<Vec<_>>().len(), 2);
+    assert_eq!(db.salsa_runtime().current_revision(), revision);
+}
@@ -0,0 +1,49 @@
+//! Test that the runtime records the history of recent revisions.
+
+use salsa::Database as _;
+
+#[salsa::query_group(RevisionHistoryStorage)]
+trait RevisionHistory: salsa::Database {
+    #[salsa::input]
+    fn input1(&self) -> usize;
+
+    #[salsa::input]
+    fn input2(&self) -> usize;
+}
+
+#[salsa::database(RevisionHistoryStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn revision_history() {
+    let mut db = Database::default();
+
+    // Nothing is recorded until a capacity is configured.
+    db.set_input1(0);
+    assert!(db.salsa_runtime().revision_history().is_empty());
+
+    db.salsa_runtime().set_revision_history_capacity(2);
+    db.set_input1(1);
+    db.set_input2(2);
+    db.salsa_runtime().next_revision();
+
+    let history = db.salsa_runtime().revision_history();
+    let changed_inputs: Vec<_> = history
+        .iter()
+        .map(|record| format!("{:?}", record.changed_input))
+        .collect();
+    assert_eq!(changed_inputs.len(), 2);
+    assert!(changed_inputs[0].contains("input2"));
+    assert_eq!(changed_inputs[1], "None");
+    assert!(history[0].revision < history[1].revision);
+    assert!(history[0].timestamp <= history[1].timestamp);
+}
@@ -0,0 +1,75 @@
+//! Test that `set_many` sets many inputs in a single revision.
+
+use salsa::debug::DebugQueryTable;
+use salsa::prelude::*;
+
+#[salsa::query_group(SetManyStorage)]
+trait SetMany: Database {
+    #[salsa::input]
+    fn input(&self, key: u32) -> u32;
+
+    fn sum(&self, keys: Vec<u32>) -> u32;
+}
+
+fn sum(db: &impl SetMany, keys: Vec<u32>) -> u32 {
+    keys.into_iter().map(|key| db.input(key)).sum()
+}
+
+#[salsa::database(SetManyStorage)]
+#[derive(Default)]
+struct DatabaseStruct {
+    runtime: Runtime<DatabaseStruct>,
+}
+
+impl Database for DatabaseStruct {
+    fn salsa_runtime(&self) -> &Runtime<DatabaseStruct> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn set_many() {
+    let mut db = DatabaseStruct::default();
+    db.salsa_runtime().set_revision_history_capacity(10);
+
+    db.query_mut(InputQuery)
+        .set_many((0..10).map(|key| (key, key)));
+    assert_eq!(db.sum((0..10).collect()), 45);
+
+    db.query_mut(InputQuery).set_many(vec![(3, 13), (4, 14)]);
+    assert_eq!(db.sum((0..10).collect()), 65);
+
+    // An empty batch does not create a revision.
+    db.query_mut(InputQuery).set_many(vec![]);
+
+    // Each batch created exactly one revision.
+    let history = db.salsa_runtime().revision_history();
+    assert_eq!(history.len(), 2);
+    assert!(format!("{:?}", history[0].changed_input).contains("input(0)"));
+    assert!(format!("{:?}", history[1].changed_input).contains("input(3)"));
+}
+
+#[test]
+#[should_panic(expected = "previously marked as constant")]
+fn set_many_constant() {
+    let mut db = DatabaseStruct::default();
+    db.query_mut(InputQuery).set_constant(1, 1);
+    db.query_mut(InputQuery).set_many(vec![(0, 10), (1, 11)]);
+}
+
+#[test]
+fn set_many_constant_sets_nothing() {
+    let mut db = DatabaseStruct::default();
+    db.query_mut(InputQuery).set(0, 0);
+    db.query_mut(InputQuery).set_constant(1, 1);
+    let revision = db.salsa_runtime().current_revision();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        db.query_mut(InputQuery)
+            .set_many(vec![(0, 10), (1, 11), (2, 12)]);
+    }));
+    assert!(result.is_err());
+    assert_eq!(db.query(InputQuery).entries::<Vec<_>>().len(), 2);
+    assert_eq!(db.input(0), 0);
+    assert_eq!(db.salsa_runtime().current_revision(), revision);
+}
@@ -4,6 +4,9 @@ pub(crate) trait Counter: salsa::Database {
 
 #[salsa::query_group(GroupStruct)]
 pub(crate) trait Database: Counter {
+    /// A memoized query.
+    ///
+    /// Reads the volatile query.
     fn memoized(&self) -> usize;
     #[salsa::volatile]
     fn volatile(&self) -> usize;
@@ -46,3 +46,15 @@ fn intermingled() {
     assert_eq!(v4 + 1, v5);
     assert_eq!(v5, v6);
 }
+
+#[test]
+fn description() {
+    use crate::queries::{MemoizedQuery, VolatileQuery};
+    use salsa::Query;
+
+    assert_eq!(
+        <MemoizedQuery as Query<DatabaseImpl>>::DESCRIPTION,
+        "A memoized query.\n\nReads the volatile query."
+    );
+    assert_eq!(<VolatileQuery as Query<DatabaseImpl>>::DESCRIPTION, "");
+}
@@ -0,0 +1,83 @@
+//! Test that `#[salsa::validate]` checks newly computed values.
+#![cfg(debug_assertions)]
+
+use salsa::Database as _;
+use std::panic::{self, AssertUnwindSafe};
+
+#[salsa::query_group(ValidateStorage)]
+trait Validate: salsa::Database {
+    #[salsa::input]
+    fn input(&self, key: u32) -> u32;
+
+    /// Should always produce an even number.
+    #[salsa::validate(check_even)]
+    fn successor(&self, key: u32) -> u32;
+
+    #[salsa::input]
+    fn limit(&self) -> u32;
+
+    /// Should never exceed `limit`.
+    #[salsa::validate(check_limit)]
+    fn double(&self, key: u32) -> u32;
+}
+
+fn successor(db: &impl Validate, key: u32) -> u32 {
+    db.input(key) + 1
+}
+
+fn check_even(_db: &impl Validate, key: &u32, value: &u32) {
+    assert!(value % 2 == 0, "odd value {} for key {}", value, key);
+}
+
+fn double(db: &impl Validate, key: u32) -> u32 {
+    db.input(key) * 2
+}
+
+fn check_limit(db: &impl Validate, _key: &u32, value: &u32) {
+    assert!(*value <= db.limit(), "{} exceeds the limit", value);
+}
+
+#[salsa::database(ValidateStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+#[test]
+fn valid_value() {
+    let mut db = Database::default();
+    db.set_input(1, 3);
+    assert_eq!(db.successor(1), 4);
+}
+
+#[test]
+fn invalid_value_panics() {
+    let mut db = Database::default();
+    db.set_input(1, 2);
+
+    let payload = panic::catch_unwind(AssertUnwindSafe(|| db.successor(1))).unwrap_err();
+    let message = payload.downcast::<String>().unwrap();
+    assert_eq!(*message, "odd value 3 for key 1");
+
+    // Once the input is fixed, the query computes normally again.
+    db.set_input(1, 5);
+    assert_eq!(db.successor(1), 6);
+}
+
+#[test]
+fn validator_reads_are_not_dependencies() {
+    let mut db = Database::default();
+    db.set_input(1, 2);
+    db.set_limit(10);
+    assert_eq!(db.double(1), 4);
+
+    db.set_limit(20);
+    assert_eq!(db.double(1), 4);
+    assert_eq!(db.query(DoubleQuery).execution_stats().executions, 1);
+}
@@ -0,0 +1,75 @@
+//! Test that `verify_all` re-executes queries and detects memos that
+//! a fresh execution would not reproduce.
+
+use salsa::Database as _;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+#[salsa::query_group(VerifyStorage)]
+trait Verify: salsa::Database + Untracked {
+    #[salsa::input]
+    fn input(&self, key: u32) -> u32;
+
+    fn double(&self, key: u32) -> u32;
+
+    fn sneaky(&self, key: u32) -> u32;
+}
+
+trait Untracked {
+    fn untracked(&self) -> u32;
+}
+
+fn double(db: &impl Verify, key: u32) -> u32 {
+    db.input(key) * 2
+}
+
+fn sneaky(db: &impl Verify, key: u32) -> u32 {
+    db.input(key) + db.untracked()
+}
+
+#[salsa::database(VerifyStorage)]
+#[derive(Default)]
+struct Database {
+    runtime: salsa::Runtime<Database>,
+    untracked: Cell<u32>,
+}
+
+impl salsa::Database for Database {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Database> {
+        &self.runtime
+    }
+}
+
+impl Untracked for Database {
+    fn untracked(&self) -> u32 {
+        self.untracked.get()
+    }
+}
+
+#[test]
+fn deterministic_queries_verify() {
+    let mut db = Database::default();
+    db.set_input(1, 10);
+    db.set_input(2, 20);
+    assert_eq!(db.double(1), 20);
+    assert_eq!(db.double(2), 40);
+
+    db.query(DoubleQuery).verify_all();
+
+    // Stale memos are not verified.
+    db.set_input(1, 11);
+    db.query(DoubleQuery).verify_all();
+}
+
+#[test]
+fn untracked_read_is_detected() {
+    let mut db = Database::default();
+    db.set_input(1, 10);
+    assert_eq!(db.sneaky(1), 10);
+    db.query(SneakyQuery).verify_all();
+
+    db.untracked.set(5);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| db.query(SneakyQuery).verify_all()));
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("not deterministic"), "{}", message);
+}